@@ -1,3 +1,3 @@
-function klf --description 'copy the content of a file to the klip clipboard'
-  klip copy < $argv[1];
+function klf --description 'copy a file to the klip clipboard, along with its name and mode'
+  klip copy $argv[1];
 end
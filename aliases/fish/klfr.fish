@@ -1,3 +1,7 @@
-function klfr --description 'send a whole directory to the klip clipboard, as a tar archive'
-  tar cJpvf - $argv | klip copy;
+function klfr --description 'send a whole directory to the klip clipboard'
+  if set -q argv[1]
+    klip copy -r $argv[1];
+  else
+    klip copy -r .;
+  end
 end
@@ -0,0 +1,7 @@
+function klpf --description 'save the klip clipboard content under its original name'
+  if set -q argv[1]
+    klip paste -o $argv[1]/;
+  else
+    klip paste -o ./;
+  end
+end
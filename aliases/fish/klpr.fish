@@ -1,3 +1,3 @@
-function klpr --description 'extract klip clipboard content sent using the klfr command'
-  klip paste | tar xJpvf -;
+function klpr --description 'extract a directory sent to the klip clipboard using the klfr command'
+  klip paste -r $argv;
 end
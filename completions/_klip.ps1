@@ -31,6 +31,8 @@ Register-ArgumentCompleter -Native -CommandName 'klip' -ScriptBlock {
       [CompletionResult]::new('copy', 'copy', [CompletionResultType]::ParameterValue, 'store content')
       [CompletionResult]::new('paste', 'paste', [CompletionResultType]::ParameterValue, 'retrieve content')
       [CompletionResult]::new('move', 'move', [CompletionResultType]::ParameterValue, 'retrieve and delete content')
+      [CompletionResult]::new('history', 'history', [CompletionResultType]::ParameterValue, 'list the stored content history')
+      [CompletionResult]::new('status', 'status', [CompletionResultType]::ParameterValue, 'describe content without retrieving it')
       [CompletionResult]::new('serve', 'serve', [CompletionResultType]::ParameterValue, 'start a server')
       [CompletionResult]::new('genkeys', 'genkeys', [CompletionResultType]::ParameterValue, 'generate keys')
       [CompletionResult]::new('version', 'version', [CompletionResultType]::ParameterValue, 'show version information')
@@ -38,18 +40,78 @@ Register-ArgumentCompleter -Native -CommandName 'klip' -ScriptBlock {
       break
     }
     'klip;copy' {
+      [CompletionResult]::new('-s', '-s', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--slot', '--slot', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--to', '--to', [CompletionResultType]::ParameterName, 'encrypt for these recipient public keys instead of the shared key')
+      [CompletionResult]::new('--name', '--name', [CompletionResultType]::ParameterName, 'the file name to record along with the content')
+      [CompletionResult]::new('--type', '--type', [CompletionResultType]::ParameterName, 'the content type to record along with the content (default=guessed)')
+      [CompletionResult]::new('-r', '-r', [CompletionResultType]::ParameterName, 'copy FILE as a directory, with everything in it')
+      [CompletionResult]::new('--recursive', '--recursive', [CompletionResultType]::ParameterName, 'copy FILE as a directory, with everything in it')
+      [CompletionResult]::new('--no-compress', '--no-compress', [CompletionResultType]::ParameterName, 'send the content as is, without trying to compress it')
       [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
       [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
+      [CompletionResult]::new('--version', '--version', [CompletionResultType]::ParameterName, 'Print version')
       break
     }
     'klip;paste' {
+      [CompletionResult]::new('-s', '-s', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--slot', '--slot', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('-i', '-i', [CompletionResultType]::ParameterName, 'the history entry to retrieve, counting back from the newest (0)')
+      [CompletionResult]::new('--index', '--index', [CompletionResultType]::ParameterName, 'the history entry to retrieve, counting back from the newest (0)')
+      [CompletionResult]::new('-o', '-o', [CompletionResultType]::ParameterName, 'save the content to a file, or to a directory under the name it was sent with')
+      [CompletionResult]::new('--output', '--output', [CompletionResultType]::ParameterName, 'save the content to a file, or to a directory under the name it was sent with')
+      [CompletionResult]::new('--exec', '--exec', [CompletionResultType]::ParameterName, 'run COMMAND for every new item, with the content on its standard input')
+      [CompletionResult]::new('--received', '--received', [CompletionResultType]::ParameterName, 'retrieve content that was sent to your recipient key')
+      [CompletionResult]::new('-r', '-r', [CompletionResultType]::ParameterName, 'extract a directory sent with copy -r into --output (default=the current directory)')
+      [CompletionResult]::new('--recursive', '--recursive', [CompletionResultType]::ParameterName, 'extract a directory sent with copy -r into --output (default=the current directory)')
+      [CompletionResult]::new('--force', '--force', [CompletionResultType]::ParameterName, 'overwrite the file or directory being saved if it already exists')
+      [CompletionResult]::new('--info', '--info', [CompletionResultType]::ParameterName, 'print the file name, type, size and mode instead of the content')
+      [CompletionResult]::new('--watch', '--watch', [CompletionResultType]::ParameterName, 'keep running, and paste every new item as soon as it is copied')
       [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
       [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
+      [CompletionResult]::new('--version', '--version', [CompletionResultType]::ParameterName, 'Print version')
       break
     }
     'klip;move' {
+      [CompletionResult]::new('-s', '-s', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--slot', '--slot', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('-i', '-i', [CompletionResultType]::ParameterName, 'the history entry to retrieve, counting back from the newest (0)')
+      [CompletionResult]::new('--index', '--index', [CompletionResultType]::ParameterName, 'the history entry to retrieve, counting back from the newest (0)')
+      [CompletionResult]::new('-o', '-o', [CompletionResultType]::ParameterName, 'save the content to a file, or to a directory under the name it was sent with')
+      [CompletionResult]::new('--output', '--output', [CompletionResultType]::ParameterName, 'save the content to a file, or to a directory under the name it was sent with')
+      [CompletionResult]::new('--received', '--received', [CompletionResultType]::ParameterName, 'retrieve content that was sent to your recipient key')
+      [CompletionResult]::new('-r', '-r', [CompletionResultType]::ParameterName, 'extract a directory sent with copy -r into --output (default=the current directory)')
+      [CompletionResult]::new('--recursive', '--recursive', [CompletionResultType]::ParameterName, 'extract a directory sent with copy -r into --output (default=the current directory)')
+      [CompletionResult]::new('--force', '--force', [CompletionResultType]::ParameterName, 'overwrite the file or directory being saved if it already exists')
+      [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
+      [CompletionResult]::new('--version', '--version', [CompletionResultType]::ParameterName, 'Print version')
+      break
+    }
+    'klip;history' {
+      [CompletionResult]::new('-s', '-s', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--slot', '--slot', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--received', '--received', [CompletionResultType]::ParameterName, 'list content that was sent to your recipient key')
+      [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
+      [CompletionResult]::new('--version', '--version', [CompletionResultType]::ParameterName, 'Print version')
+      break
+    }
+    'klip;status' {
+      [CompletionResult]::new('-s', '-s', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('--slot', '--slot', [CompletionResultType]::ParameterName, 'the clipboard slot to operate on (default=the unnamed slot)')
+      [CompletionResult]::new('-i', '-i', [CompletionResultType]::ParameterName, 'the history entry to describe, counting back from the newest (0)')
+      [CompletionResult]::new('--index', '--index', [CompletionResultType]::ParameterName, 'the history entry to describe, counting back from the newest (0)')
+      [CompletionResult]::new('--received', '--received', [CompletionResultType]::ParameterName, 'describe content that was sent to your recipient key')
+      [CompletionResult]::new('--json', '--json', [CompletionResultType]::ParameterName, 'print a JSON object instead of text')
       [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
       [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
+      [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
+      [CompletionResult]::new('--version', '--version', [CompletionResultType]::ParameterName, 'Print version')
       break
     }
     'klip;serve' {
@@ -59,6 +121,9 @@ Register-ArgumentCompleter -Native -CommandName 'klip' -ScriptBlock {
       [CompletionResult]::new('--timeout', '--timeout', [CompletionResultType]::ParameterName, 'connection timeout (in seconds)')
       [CompletionResult]::new('-d', '-d', [CompletionResultType]::ParameterName, 'data transmission timeout (in seconds)')
       [CompletionResult]::new('--data-timeout', '--data-timeout', [CompletionResultType]::ParameterName, 'data transmission timeout (in seconds)')
+      [CompletionResult]::new('--grace-period', '--grace-period', [CompletionResultType]::ParameterName, 'how long open connections get to finish when shutting down (in seconds)')
+      [CompletionResult]::new('--log-level', '--log-level', [CompletionResultType]::ParameterName, 'the least severe messages to log (default=info)')
+      [CompletionResult]::new('--log-format', '--log-format', [CompletionResultType]::ParameterName, 'the format to log messages in (default=text)')
       [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
       [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
       [CompletionResult]::new('-V', '-V ', [CompletionResultType]::ParameterName, 'Print version')
@@ -83,6 +148,8 @@ Register-ArgumentCompleter -Native -CommandName 'klip' -ScriptBlock {
       [CompletionResult]::new('copy', 'copy', [CompletionResultType]::ParameterValue, 'store content')
       [CompletionResult]::new('paste', 'paste', [CompletionResultType]::ParameterValue, 'retrieve content')
       [CompletionResult]::new('move', 'move', [CompletionResultType]::ParameterValue, 'retrieve and delete content')
+      [CompletionResult]::new('history', 'history', [CompletionResultType]::ParameterValue, 'list the stored content history')
+      [CompletionResult]::new('status', 'status', [CompletionResultType]::ParameterValue, 'describe content without retrieving it')
       [CompletionResult]::new('serve', 'serve', [CompletionResultType]::ParameterValue, 'start a server')
       [CompletionResult]::new('genkeys', 'genkeys', [CompletionResultType]::ParameterValue, 'generate keys')
       [CompletionResult]::new('version', 'version', [CompletionResultType]::ParameterValue, 'show version information')
@@ -98,6 +165,12 @@ Register-ArgumentCompleter -Native -CommandName 'klip' -ScriptBlock {
     'klip;help;move' {
       break
     }
+    'klip;help;history' {
+      break
+    }
+    'klip;help;status' {
+      break
+    }
     'klip;help;serve' {
       break
     }
@@ -21,6 +21,9 @@ _klip() {
       klip,help)
         cmd="klip__help"
         ;;
+      klip,history)
+        cmd="klip__history"
+        ;;
       klip,move)
         cmd="klip__move"
         ;;
@@ -30,6 +33,9 @@ _klip() {
       klip,serve)
         cmd="klip__serve"
         ;;
+      klip,status)
+        cmd="klip__status"
+        ;;
       klip,version)
         cmd="klip__version"
         ;;
@@ -42,6 +48,9 @@ _klip() {
       klip__help,help)
         cmd="klip__help__help"
         ;;
+      klip__help,history)
+        cmd="klip__help__history"
+        ;;
       klip__help,move)
         cmd="klip__help__move"
         ;;
@@ -51,6 +60,9 @@ _klip() {
       klip__help,serve)
         cmd="klip__help__serve"
         ;;
+      klip__help,status)
+        cmd="klip__help__status"
+        ;;
       klip__help,version)
         cmd="klip__help__version"
         ;;
@@ -61,7 +73,7 @@ _klip() {
 
   case "${cmd}" in
     klip)
-      opts="-c -h -V --config --help --version copy paste move serve genkeys version help"
+      opts="-c -h -V --config --help --version copy paste move history status serve genkeys version help"
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 1 ]] ; then
         COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
         return 0
@@ -83,12 +95,32 @@ _klip() {
       return 0
       ;;
     klip__copy)
-      opts="-h --help"
+      opts="-s -r -h -V --slot --recursive --to --no-compress --name --type --help --version [FILE]"
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
         COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
         return 0
       fi
       case "${prev}" in
+        --slot)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -s)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --to)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --name)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --type)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
         *)
           COMPREPLY=()
           ;;
@@ -111,7 +143,7 @@ _klip() {
       return 0
       ;;
     klip__help)
-      opts="copy paste move serve genkeys version help"
+      opts="copy paste move history status serve genkeys version help"
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
         COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
         return 0
@@ -166,6 +198,20 @@ _klip() {
       COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
       return 0
       ;;
+    klip__help__history)
+      opts=""
+      if [[ ${cur} == -* || ${COMP_CWORD} -eq 3 ]] ; then
+        COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        return 0
+      fi
+      case "${prev}" in
+        *)
+          COMPREPLY=()
+          ;;
+      esac
+      COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+      return 0
+      ;;
     klip__help__move)
       opts=""
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 3 ]] ; then
@@ -208,6 +254,20 @@ _klip() {
       COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
       return 0
       ;;
+    klip__help__status)
+      opts=""
+      if [[ ${cur} == -* || ${COMP_CWORD} -eq 3 ]] ; then
+        COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        return 0
+      fi
+      case "${prev}" in
+        *)
+          COMPREPLY=()
+          ;;
+      esac
+      COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+      return 0
+      ;;
     klip__help__version)
       opts=""
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 3 ]] ; then
@@ -222,13 +282,59 @@ _klip() {
       COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
       return 0
       ;;
-    klip__move)
-      opts="-h --help"
+    klip__history)
+      opts="-s -h -V --slot --received --help --version"
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
-          COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        return 0
+      fi
+      case "${prev}" in
+        --slot)
+          COMPREPLY=($(compgen -f "${cur}"))
           return 0
+          ;;
+        -s)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        *)
+          COMPREPLY=()
+          ;;
+      esac
+      COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+      return 0
+      ;;
+    klip__move)
+      opts="-s -i -o -r -h -V --slot --index --received --output --recursive --force --help --version"
+      if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
+        COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        return 0
       fi
       case "${prev}" in
+        --slot)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -s)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --index)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -i)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --output)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -o)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
         *)
           COMPREPLY=()
           ;;
@@ -237,12 +343,40 @@ _klip() {
       return 0
       ;;
     klip__paste)
-      opts="-h --help"
+      opts="-s -i -o -r -h -V --slot --index --received --output --recursive --force --info --watch --exec --help --version"
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
         COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
         return 0
       fi
       case "${prev}" in
+        --slot)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -s)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --index)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -i)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --output)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -o)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --exec)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
         *)
           COMPREPLY=()
           ;;
@@ -251,10 +385,10 @@ _klip() {
       return 0
       ;;
     klip__serve)
-      opts="-t -d -h -V --max-clients --max-len-mb --timeout --data-timeout --help --version"
+      opts="-t -d -h -V --max-clients --max-len-mb --timeout --data-timeout --grace-period --log-level --log-format --help --version"
       if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
-          COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
-          return 0
+        COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        return 0
       fi
       case "${prev}" in
         --max-clients)
@@ -281,6 +415,48 @@ _klip() {
           COMPREPLY=($(compgen -f "${cur}"))
           return 0
           ;;
+        --grace-period)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --log-level)
+          COMPREPLY=($(compgen -W "error warn info debug" -- "${cur}"))
+          return 0
+          ;;
+        --log-format)
+          COMPREPLY=($(compgen -W "text json" -- "${cur}"))
+          return 0
+          ;;
+        *)
+          COMPREPLY=()
+          ;;
+      esac
+      COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+      return 0
+      ;;
+    klip__status)
+      opts="-s -i -h -V --slot --index --received --json --help --version"
+      if [[ ${cur} == -* || ${COMP_CWORD} -eq 2 ]] ; then
+        COMPREPLY=( $(compgen -W "${opts}" -- "${cur}") )
+        return 0
+      fi
+      case "${prev}" in
+        --slot)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -s)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        --index)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
+        -i)
+          COMPREPLY=($(compgen -f "${cur}"))
+          return 0
+          ;;
         *)
           COMPREPLY=()
           ;;
@@ -30,27 +30,68 @@ complete -c klip -n "__fish_klip_needs_command" -s V -l version -d 'Print versio
 complete -c klip -n "__fish_klip_needs_command" -f -a "copy" -d 'store content'
 complete -c klip -n "__fish_klip_needs_command" -f -a "paste" -d 'retrieve content'
 complete -c klip -n "__fish_klip_needs_command" -f -a "move" -d 'retrieve and delete content'
+complete -c klip -n "__fish_klip_needs_command" -f -a "history" -d 'list the stored content history'
+complete -c klip -n "__fish_klip_needs_command" -f -a "status" -d 'describe content without retrieving it'
 complete -c klip -n "__fish_klip_needs_command" -f -a "serve" -d 'start a server'
 complete -c klip -n "__fish_klip_needs_command" -f -a "genkeys" -d 'generate keys'
 complete -c klip -n "__fish_klip_needs_command" -f -a "version" -d 'show version information'
 complete -c klip -n "__fish_klip_needs_command" -f -a "help" -d 'Print this message or the help of the given subcommand(s)'
+complete -c klip -n "__fish_klip_using_subcommand copy" -s s -l slot -d 'the clipboard slot to operate on (default=the unnamed slot)' -r
+complete -c klip -n "__fish_klip_using_subcommand copy" -l to -d 'encrypt for these recipient public keys instead of the shared key' -r
+complete -c klip -n "__fish_klip_using_subcommand copy" -l name -d 'the file name to record along with the content' -r
+complete -c klip -n "__fish_klip_using_subcommand copy" -l type -d 'the content type to record along with the content (default=guessed)' -r
+complete -c klip -n "__fish_klip_using_subcommand copy" -s r -l recursive -d 'copy FILE as a directory, with everything in it'
+complete -c klip -n "__fish_klip_using_subcommand copy" -l no-compress -d 'send the content as is, without trying to compress it'
 complete -c klip -n "__fish_klip_using_subcommand copy" -s h -l help -d 'Print help'
+complete -c klip -n "__fish_klip_using_subcommand copy" -s V -l version -d 'Print version'
+complete -c klip -n "__fish_klip_using_subcommand paste" -s s -l slot -d 'the clipboard slot to operate on (default=the unnamed slot)' -r
+complete -c klip -n "__fish_klip_using_subcommand paste" -s i -l index -d 'the history entry to retrieve, counting back from the newest (0)' -r
+complete -c klip -n "__fish_klip_using_subcommand paste" -s o -l output -d 'save the content to a file, or to a directory under the name it was sent with' -r -F
+complete -c klip -n "__fish_klip_using_subcommand paste" -l exec -d 'run COMMAND for every new item, with the content on its standard input' -r
+complete -c klip -n "__fish_klip_using_subcommand paste" -l received -d 'retrieve content that was sent to your recipient key'
+complete -c klip -n "__fish_klip_using_subcommand paste" -s r -l recursive -d 'extract a directory sent with copy -r into --output (default=the current directory)'
+complete -c klip -n "__fish_klip_using_subcommand paste" -l force -d 'overwrite the file or directory being saved if it already exists'
+complete -c klip -n "__fish_klip_using_subcommand paste" -l info -d 'print the file name, type, size and mode instead of the content'
+complete -c klip -n "__fish_klip_using_subcommand paste" -l watch -d 'keep running, and paste every new item as soon as it is copied'
 complete -c klip -n "__fish_klip_using_subcommand paste" -s h -l help -d 'Print help'
+complete -c klip -n "__fish_klip_using_subcommand paste" -s V -l version -d 'Print version'
+complete -c klip -n "__fish_klip_using_subcommand move" -s s -l slot -d 'the clipboard slot to operate on (default=the unnamed slot)' -r
+complete -c klip -n "__fish_klip_using_subcommand move" -s i -l index -d 'the history entry to retrieve, counting back from the newest (0)' -r
+complete -c klip -n "__fish_klip_using_subcommand move" -s o -l output -d 'save the content to a file, or to a directory under the name it was sent with' -r -F
+complete -c klip -n "__fish_klip_using_subcommand move" -l received -d 'retrieve content that was sent to your recipient key'
+complete -c klip -n "__fish_klip_using_subcommand move" -s r -l recursive -d 'extract a directory sent with copy -r into --output (default=the current directory)'
+complete -c klip -n "__fish_klip_using_subcommand move" -l force -d 'overwrite the file or directory being saved if it already exists'
 complete -c klip -n "__fish_klip_using_subcommand move" -s h -l help -d 'Print help'
+complete -c klip -n "__fish_klip_using_subcommand move" -s V -l version -d 'Print version'
+complete -c klip -n "__fish_klip_using_subcommand history" -s s -l slot -d 'the clipboard slot to operate on (default=the unnamed slot)' -r
+complete -c klip -n "__fish_klip_using_subcommand history" -l received -d 'list content that was sent to your recipient key'
+complete -c klip -n "__fish_klip_using_subcommand history" -s h -l help -d 'Print help'
+complete -c klip -n "__fish_klip_using_subcommand history" -s V -l version -d 'Print version'
+complete -c klip -n "__fish_klip_using_subcommand status" -s s -l slot -d 'the clipboard slot to operate on (default=the unnamed slot)' -r
+complete -c klip -n "__fish_klip_using_subcommand status" -s i -l index -d 'the history entry to describe, counting back from the newest (0)' -r
+complete -c klip -n "__fish_klip_using_subcommand status" -l received -d 'describe content that was sent to your recipient key'
+complete -c klip -n "__fish_klip_using_subcommand status" -l json -d 'print a JSON object instead of text'
+complete -c klip -n "__fish_klip_using_subcommand status" -s h -l help -d 'Print help'
+complete -c klip -n "__fish_klip_using_subcommand status" -s V -l version -d 'Print version'
 complete -c klip -n "__fish_klip_using_subcommand serve" -l max-clients -d 'the maximum number of simultaneous client connections' -r
 complete -c klip -n "__fish_klip_using_subcommand serve" -l max-len-mb -d 'maximum content length to accept in MiB (0=unlimited)' -r
 complete -c klip -n "__fish_klip_using_subcommand serve" -s t -l timeout -d 'connection timeout (in seconds)' -r
 complete -c klip -n "__fish_klip_using_subcommand serve" -s d -l data-timeout -d 'data transmission timeout (in seconds)' -r
+complete -c klip -n "__fish_klip_using_subcommand serve" -l grace-period -d 'how long open connections get to finish when shutting down (in seconds)' -r
+complete -c klip -n "__fish_klip_using_subcommand serve" -l log-level -d 'the least severe messages to log (default=info)' -r -f -a "{error\t'',warn\t'',info\t'',debug\t''}"
+complete -c klip -n "__fish_klip_using_subcommand serve" -l log-format -d 'the format to log messages in (default=text)' -r -f -a "{text\t'',json\t''}"
 complete -c klip -n "__fish_klip_using_subcommand serve" -s h -l help -d 'Print help'
 complete -c klip -n "__fish_klip_using_subcommand serve" -s V -l version -d 'Print version'
 complete -c klip -n "__fish_klip_using_subcommand genkeys" -s p -l password -d 'derive the keys from a password (default=random keys)'
 complete -c klip -n "__fish_klip_using_subcommand genkeys" -s h -l help -d 'Print help'
 complete -c klip -n "__fish_klip_using_subcommand genkeys" -s V -l version -d 'Print version'
 complete -c klip -n "__fish_klip_using_subcommand version" -s h -l help -d 'Print help'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "copy" -d 'store content'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "paste" -d 'retrieve content'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "move" -d 'retrieve and delete content'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "serve" -d 'start a server'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "genkeys" -d 'generate keys'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "version" -d 'show version information'
-complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move serve genkeys version help" -f -a "help" -d 'Print this message or the help of the given subcommand(s)'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "copy" -d 'store content'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "paste" -d 'retrieve content'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "move" -d 'retrieve and delete content'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "history" -d 'list the stored content history'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "status" -d 'describe content without retrieving it'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "serve" -d 'start a server'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "genkeys" -d 'generate keys'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "version" -d 'show version information'
+complete -c klip -n "__fish_klip_using_subcommand help; and not __fish_seen_subcommand_from copy paste move history status serve genkeys version help" -f -a "help" -d 'Print this message or the help of the given subcommand(s)'
@@ -21,6 +21,11 @@ pub const SQRT_M1: FieldElement2625 = FieldElement2625::from_limbs([
     326_685, 11_406_482,
 ]);
 
+// (A - 2) / 4, where A = 486662 is the Montgomery curve coefficient. RFC 7748
+// calls it a24.
+pub const A24: FieldElement2625 =
+    FieldElement2625::from_limbs([121_665, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
 pub const L: Scalar29 = Scalar29([
     0x1cf5_d3ed,
     0x0093_18d2,
@@ -29,6 +29,10 @@ pub const SQRT_M1: FieldElement51 = FieldElement51::from_limbs([
     765_476_049_583_133,
 ]);
 
+// (A - 2) / 4, where A = 486662 is the Montgomery curve coefficient. RFC 7748
+// calls it a24.
+pub const A24: FieldElement51 = FieldElement51::from_limbs([121_665, 0, 0, 0, 0]);
+
 pub const L: Scalar52 = Scalar52([
     0x0002_631a_5cf5_d3ed,
     0x000d_ea2f_79cd_6581,
@@ -41,6 +41,8 @@ use crypto_common::{
 mod backends;
 use backends::serial::curve_models::{AffineNielsPoint, ProjectiveNielsPoint, ProjectivePoint};
 mod consts;
+pub mod montgomery;
+pub use montgomery::MontgomeryPoint;
 #[cfg(curve25519_report)]
 mod report;
 mod window;
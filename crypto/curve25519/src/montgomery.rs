@@ -0,0 +1,215 @@
+//! The Montgomery form of Curve25519, and X25519 as described in RFC 7748.
+//!
+//! Only the u-coordinate of a point is kept, which is all the ladder needs.
+
+use crate::{
+    consts, scalar::clamp_integer, CompressedEdwardsY, EdwardsPoint, FieldElement, Scalar,
+};
+use core::fmt::Debug;
+use crypto_common::{
+    constant_time::{Choice, ConditionallySelectable, ConstantTimeEq},
+    erase::Erase,
+};
+
+/// The u-coordinate of the standard base point.
+pub const X25519_BASEPOINT: MontgomeryPoint = MontgomeryPoint([
+    9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+]);
+
+#[derive(Clone, Copy, Default)]
+pub struct MontgomeryPoint(pub [u8; 32]);
+
+impl MontgomeryPoint {
+    #[must_use]
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Multiplies the point by `bytes`, clamped as RFC 7748 requires, in
+    /// constant time.
+    #[must_use]
+    pub fn mul_clamped(self, bytes: [u8; 32]) -> Self {
+        ladder(&FieldElement::from_bytes(&self.0), &clamp_integer(bytes))
+    }
+
+    /// Multiplies the base point by `bytes`, clamped as RFC 7748 requires.
+    #[must_use]
+    pub fn mul_base_clamped(bytes: [u8; 32]) -> Self {
+        // a clamped scalar is a multiple of the cofactor, and the base point has
+        // prime order, so reducing it first doesn't change the result.
+        let scalar = Scalar::from_bytes_mod_order(clamp_integer(bytes));
+        EdwardsPoint::mul_base(&scalar).to_montgomery()
+    }
+
+    /// Maps the point to Edwards form. The u-coordinate determines the point
+    /// up to its sign, which has to be supplied separately.
+    ///
+    /// Returns `None` if `u = -1`, which has no Edwards counterpart, or if the
+    /// u-coordinate is not on the curve at all.
+    #[must_use]
+    pub fn to_edwards(&self, sign: u8) -> Option<EdwardsPoint> {
+        // y = (u - 1) / (u + 1)
+        let u = FieldElement::from_bytes(&self.0);
+        if u == -&FieldElement::ONE {
+            return None;
+        }
+        let y = &(&u - &FieldElement::ONE) * &(&u + &FieldElement::ONE).invert();
+        let mut y = y.as_bytes();
+        y[31] ^= sign << 7;
+        CompressedEdwardsY(y).decompress()
+    }
+}
+
+impl EdwardsPoint {
+    /// Maps the point to Montgomery form, dropping the sign.
+    #[must_use]
+    pub fn to_montgomery(&self) -> MontgomeryPoint {
+        // u = (1 + y) / (1 - y) = (Z + Y) / (Z - Y). the identity maps to 0,
+        // since inverting 0 gives 0.
+        let u = &(&self.z + &self.y) * &(&self.z - &self.y).invert();
+        MontgomeryPoint(u.as_bytes())
+    }
+}
+
+/// The X25519 function from RFC 7748: multiplies the u-coordinate `u` by the
+/// clamped scalar `k`.
+///
+/// The result is all zeroes if `u` is a point of small order. Protocols that
+/// need contributory behaviour have to check for that themselves.
+#[must_use]
+pub fn x25519(k: [u8; 32], u: [u8; 32]) -> [u8; 32] {
+    MontgomeryPoint(u).mul_clamped(k).to_bytes()
+}
+
+// the Montgomery ladder from RFC 7748, section 5. `k` must already be clamped.
+// the same operations run for every bit, and the conditional swaps don't
+// branch, so the time taken doesn't depend on the scalar.
+#[allow(clippy::similar_names)]
+fn ladder(u: &FieldElement, k: &[u8; 32]) -> MontgomeryPoint {
+    let mut x2 = FieldElement::ONE;
+    let mut z2 = FieldElement::ZERO;
+    let mut x3 = *u;
+    let mut z3 = FieldElement::ONE;
+    let mut swap = Choice::from(0);
+    for t in (0..255).rev() {
+        let bit = Choice::from((k[t / 8] >> (t % 8)) & 1);
+        swap ^= bit;
+        FieldElement::conditional_swap(&mut x2, &mut x3, swap);
+        FieldElement::conditional_swap(&mut z2, &mut z3, swap);
+        swap = bit;
+        let a = &x2 + &z2;
+        let aa = a.square();
+        let b = &x2 - &z2;
+        let bb = b.square();
+        let e = &aa - &bb;
+        let c = &x3 + &z3;
+        let d = &x3 - &z3;
+        let da = &d * &a;
+        let cb = &c * &b;
+        x3 = (&da + &cb).square();
+        z3 = u * &(&da - &cb).square();
+        x2 = &aa * &bb;
+        z2 = &e * &(&aa + &(&consts::A24 * &e));
+    }
+    FieldElement::conditional_swap(&mut x2, &mut x3, swap);
+    FieldElement::conditional_swap(&mut z2, &mut z3, swap);
+    MontgomeryPoint((&x2 * &z2.invert()).as_bytes())
+}
+
+// non-canonical encodings of the same u-coordinate compare equal.
+impl ConstantTimeEq for MontgomeryPoint {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        FieldElement::from_bytes(&self.0).ct_eq(&FieldElement::from_bytes(&other.0))
+    }
+}
+
+impl PartialEq for MontgomeryPoint {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for MontgomeryPoint {}
+
+impl Erase for MontgomeryPoint {
+    fn erase(&mut self) {
+        self.0.erase();
+    }
+}
+
+impl Debug for MontgomeryPoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "MontgomeryPoint: {:?}", self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(s: &str) -> [u8; 32] {
+        let mut out = [0; 32];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    // RFC 7748, section 5.2.
+    #[test]
+    fn test_rfc7748_vectors() {
+        let k = from_hex("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4");
+        let u = from_hex("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c");
+        assert_eq!(
+            x25519(k, u),
+            from_hex("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+        );
+        let k = from_hex("4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d");
+        let u = from_hex("e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493");
+        assert_eq!(
+            x25519(k, u),
+            from_hex("95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957")
+        );
+    }
+
+    // RFC 7748, section 6.1.
+    #[test]
+    fn test_rfc7748_diffie_hellman() {
+        let a = from_hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let b = from_hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let big_a = MontgomeryPoint::mul_base_clamped(a);
+        let big_b = MontgomeryPoint::mul_base_clamped(b);
+        assert_eq!(
+            big_a.0,
+            from_hex("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+        assert_eq!(
+            big_b.0,
+            from_hex("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+        );
+        assert_eq!(X25519_BASEPOINT.mul_clamped(a), big_a);
+        let shared = from_hex("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(a, big_b.0), shared);
+        assert_eq!(x25519(b, big_a.0), shared);
+    }
+
+    #[test]
+    fn test_edwards_roundtrip() {
+        let mut one = [0; 32];
+        one[0] = 1;
+        let base = EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(one));
+        assert_eq!(base.to_montgomery(), X25519_BASEPOINT);
+        for bytes in [[7; 32], [0xa5; 32]] {
+            let p = EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(bytes));
+            let sign = p.compress().as_bytes()[31] >> 7;
+            assert_eq!(p.to_montgomery().to_edwards(sign), Some(p));
+            assert_eq!(p.to_montgomery().to_edwards(sign ^ 1), Some(-p));
+        }
+        assert_eq!(EdwardsPoint::default().to_montgomery().0, [0; 32]);
+    }
+}
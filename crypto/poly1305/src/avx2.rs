@@ -0,0 +1,115 @@
+use crate::{
+    portable::{self, State},
+    Block,
+};
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{
+    __m256i, _mm256_add_epi64, _mm256_and_si256, _mm256_loadu_si256, _mm256_mul_epu32,
+    _mm256_set1_epi64x, _mm256_setzero_si256, _mm256_slli_epi64, _mm256_srli_epi64,
+    _mm256_storeu_si256,
+};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{
+    __m256i, _mm256_add_epi64, _mm256_and_si256, _mm256_loadu_si256, _mm256_mul_epu32,
+    _mm256_set1_epi64x, _mm256_setzero_si256, _mm256_slli_epi64, _mm256_srli_epi64,
+    _mm256_storeu_si256,
+};
+
+pub const PAR_BLOCKS: usize = 4;
+
+// every lane holds one of four interleaved accumulators, one 26-bit limb per
+// vector. lane i absorbs blocks i, i + 4, i + 8, ..., multiplying by r^4 in
+// between. at the end, lane i is multiplied by r^(4 - i) and the lanes are
+// summed, which gives the same result as the serial Horner evaluation.
+//
+// `blocks.len()` must be a multiple of `PAR_BLOCKS`.
+#[target_feature(enable = "avx2")]
+pub unsafe fn blocks(state: &mut State, blocks: &[Block]) {
+    debug_assert!(!blocks.is_empty() && blocks.len().is_multiple_of(PAR_BLOCKS));
+    let r = state.r;
+    let r2 = portable::mul(&r, &r);
+    let r3 = portable::mul(&r2, &r);
+    let r4 = portable::mul(&r2, &r2);
+    let r4v = r4.map(|l| splat(l));
+    let rpow = [0, 1, 2, 3, 4].map(|i| lanes([r4[i], r3[i], r2[i], r[i]].map(u64::from)));
+    let mut acc = state.h.map(|l| lanes([u64::from(l), 0, 0, 0]));
+    let mut groups = blocks.chunks(PAR_BLOCKS).peekable();
+    while let Some(group) = groups.next() {
+        let m = [
+            portable::limbs(&group[0], portable::HIBIT),
+            portable::limbs(&group[1], portable::HIBIT),
+            portable::limbs(&group[2], portable::HIBIT),
+            portable::limbs(&group[3], portable::HIBIT),
+        ];
+        for (i, acc) in acc.iter_mut().enumerate() {
+            let m = lanes([m[0][i], m[1][i], m[2][i], m[3][i]].map(u64::from));
+            *acc = _mm256_add_epi64(*acc, m);
+        }
+        acc = if groups.peek().is_some() {
+            mul(&acc, &r4v)
+        } else {
+            mul(&acc, &rpow)
+        };
+    }
+    let mut sum = [0; 5];
+    for (sum, acc) in sum.iter_mut().zip(acc) {
+        *sum = unlanes(acc).iter().sum();
+    }
+    state.h = portable::carry(sum);
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+#[allow(clippy::cast_possible_wrap)]
+unsafe fn splat(limb: u32) -> __m256i {
+    _mm256_set1_epi64x(i64::from(limb))
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn lanes(v: [u64; 4]) -> __m256i {
+    _mm256_loadu_si256(v.as_ptr().cast())
+}
+
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn unlanes(v: __m256i) -> [u64; 4] {
+    let mut out = [0; 4];
+    _mm256_storeu_si256(out.as_mut_ptr().cast(), v);
+    out
+}
+
+// lane-wise a * b mod 2^130 - 5, only partially reduced, like `portable::mul`.
+#[inline]
+#[target_feature(enable = "avx2")]
+unsafe fn mul(a: &[__m256i; 5], b: &[__m256i; 5]) -> [__m256i; 5] {
+    let five = |x| _mm256_add_epi64(x, _mm256_slli_epi64(x, 2));
+    let [b0, b1, b2, b3, b4] = *b;
+    let (s1, s2, s3, s4) = (five(b1), five(b2), five(b3), five(b4));
+    let dot = |x: [(__m256i, __m256i); 5]| {
+        x.iter().fold(_mm256_setzero_si256(), |acc, &(a, b)| {
+            _mm256_add_epi64(acc, _mm256_mul_epu32(a, b))
+        })
+    };
+    let [a0, a1, a2, a3, a4] = *a;
+    let mut d = [
+        dot([(a0, b0), (a1, s4), (a2, s3), (a3, s2), (a4, s1)]),
+        dot([(a0, b1), (a1, b0), (a2, s4), (a3, s3), (a4, s2)]),
+        dot([(a0, b2), (a1, b1), (a2, b0), (a3, s4), (a4, s3)]),
+        dot([(a0, b3), (a1, b2), (a2, b1), (a3, b0), (a4, s4)]),
+        dot([(a0, b4), (a1, b3), (a2, b2), (a3, b1), (a4, b0)]),
+    ];
+    let mask = splat(0x03ff_ffff);
+    for i in 0..4 {
+        let c = _mm256_srli_epi64(d[i], 26);
+        d[i] = _mm256_and_si256(d[i], mask);
+        d[i + 1] = _mm256_add_epi64(d[i + 1], c);
+    }
+    let c = _mm256_srli_epi64(d[4], 26);
+    d[4] = _mm256_and_si256(d[4], mask);
+    d[0] = _mm256_add_epi64(d[0], five(c));
+    let c = _mm256_srli_epi64(d[0], 26);
+    d[0] = _mm256_and_si256(d[0], mask);
+    d[1] = _mm256_add_epi64(d[1], c);
+    d
+}
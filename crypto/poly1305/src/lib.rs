@@ -0,0 +1,218 @@
+#![no_std]
+#![deny(
+    dead_code,
+    deprecated,
+    future_incompatible,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    nonstandard_style,
+    rust_2018_idioms,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused,
+    clippy::all,
+    clippy::pedantic,
+    clippy::nursery
+)]
+#![allow(clippy::inline_always)]
+
+use crypto_common::erase::{Erase, EraseOnDrop};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx2;
+mod portable;
+
+pub const KEY_SIZE: usize = 32;
+pub const BLOCK_SIZE: usize = 16;
+pub const TAG_SIZE: usize = 16;
+
+pub type Key = [u8; KEY_SIZE];
+pub type Tag = [u8; TAG_SIZE];
+type Block = [u8; BLOCK_SIZE];
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+target_features::detect!(avx2_cpuid, "avx2");
+
+/// A one-time authenticator. The key must never be used for more than one
+/// message.
+pub struct Poly1305 {
+    state: portable::State,
+    buffer: Block,
+    pos: usize,
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    avx2: bool,
+}
+
+impl Poly1305 {
+    #[inline]
+    #[must_use]
+    pub fn new(key: &Key) -> Self {
+        Self {
+            state: portable::State::new(key),
+            buffer: [0; BLOCK_SIZE],
+            pos: 0,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            avx2: avx2_cpuid::get(),
+        }
+    }
+
+    fn blocks(&mut self, mut blocks: &[Block]) {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if self.avx2 && blocks.len() >= avx2::PAR_BLOCKS {
+            let n = blocks.len() - blocks.len() % avx2::PAR_BLOCKS;
+            unsafe { avx2::blocks(&mut self.state, &blocks[..n]) };
+            blocks = &blocks[n..];
+        }
+        for block in blocks {
+            self.state.block(block, portable::HIBIT);
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.pos > 0 {
+            let n = data.len().min(BLOCK_SIZE - self.pos);
+            self.buffer[self.pos..self.pos + n].copy_from_slice(&data[..n]);
+            self.pos += n;
+            data = &data[n..];
+            if self.pos < BLOCK_SIZE {
+                return;
+            }
+            let block = self.buffer;
+            self.state.block(&block, portable::HIBIT);
+            self.pos = 0;
+        }
+        let (blocks, rest) = data.as_chunks();
+        self.blocks(blocks);
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.pos = rest.len();
+    }
+
+    /// Like [`update`](Self::update), but then pads everything absorbed so far
+    /// with zeroes up to a multiple of [`BLOCK_SIZE`], as AEAD constructions
+    /// do for the associated data and the ciphertext.
+    pub fn update_padded(&mut self, data: &[u8]) {
+        self.update(data);
+        if self.pos > 0 {
+            self.buffer[self.pos..].fill(0);
+            let block = self.buffer;
+            self.state.block(&block, portable::HIBIT);
+            self.pos = 0;
+        }
+    }
+
+    #[must_use]
+    pub fn finalize(mut self) -> Tag {
+        if self.pos > 0 {
+            self.buffer[self.pos] = 1;
+            self.buffer[self.pos + 1..].fill(0);
+            let block = self.buffer;
+            self.state.block(&block, 0);
+        }
+        self.state.finalize()
+    }
+}
+
+impl Drop for Poly1305 {
+    fn drop(&mut self) {
+        self.buffer.erase();
+    }
+}
+
+impl EraseOnDrop for Poly1305 {}
+
+impl core::fmt::Debug for Poly1305 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Poly1305")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex<const N: usize>(s: &str) -> [u8; N] {
+        let mut out = [0; N];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    // RFC 8439, section 2.5.2.
+    #[test]
+    fn test_rfc8439() {
+        let key = from_hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+        let mut mac = Poly1305::new(&key);
+        mac.update(b"Cryptographic Forum Research Group");
+        assert_eq!(mac.finalize(), from_hex("a8061dc1305136c6c22b8baf0c0127a9"));
+    }
+
+    // long inputs go through the vectorized backend where available, a block at
+    // a time never does.
+    #[test]
+    fn test_incremental() {
+        let key = from_hex("0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20");
+        let mut data = [0; 1000];
+        for (i, b) in data.iter_mut().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            let i = i as u8;
+            *b = i.wrapping_mul(151).wrapping_add(7);
+        }
+        for len in [0, 1, 15, 16, 17, 63, 64, 65, 127, 128, 200, 1000] {
+            let mut whole = Poly1305::new(&key);
+            whole.update(&data[..len]);
+            let mut pieces = Poly1305::new(&key);
+            for piece in data[..len].chunks(7) {
+                pieces.update(piece);
+            }
+            assert_eq!(whole.finalize(), pieces.finalize(), "length {len}");
+        }
+    }
+
+    // the vectorized backend has to agree with the portable one whatever the
+    // number of blocks, including those left over after the last group of
+    // PAR_BLOCKS.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn test_avx2() {
+        if !avx2_cpuid::get() {
+            return;
+        }
+        let mut data = [0; 2048];
+        for (i, b) in data.iter_mut().enumerate() {
+            #[allow(clippy::cast_possible_truncation)]
+            let i = i as u8;
+            *b = i.wrapping_mul(73).wrapping_add(41) ^ (i >> 3);
+        }
+        let keys = [
+            from_hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b"),
+            [0xff; KEY_SIZE],
+            from_hex("0000000000000000000000000000000036e5f6b5c5e06070f0efca96227a863e"),
+        ];
+        for key in &keys {
+            for blocks in 4..=40 {
+                for extra in [0, 1, 15] {
+                    let len = blocks * BLOCK_SIZE + extra;
+                    let mut avx2 = Poly1305::new(key);
+                    assert!(avx2.avx2);
+                    avx2.update(&data[..len]);
+                    let mut portable = Poly1305::new(key);
+                    portable.avx2 = false;
+                    portable.update(&data[..len]);
+                    assert_eq!(avx2.finalize(), portable.finalize(), "length {len}");
+                }
+            }
+            let mut avx2 = Poly1305::new(key);
+            avx2.update(&data);
+            let mut portable = Poly1305::new(key);
+            portable.avx2 = false;
+            portable.update(&data);
+            assert_eq!(
+                avx2.finalize(),
+                portable.finalize(),
+                "length {}",
+                data.len()
+            );
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{Block, Key, Tag};
+use crypto_common::erase::{Erase, EraseOnDrop};
+
+const MASK: u32 = 0x03ff_ffff;
+pub const HIBIT: u32 = 1 << 24;
+
+#[inline(always)]
+const fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// splits a block into five 26-bit limbs. `hibit` is the 2^128 bit, which is
+// set for every block except a padded final one.
+#[inline(always)]
+pub fn limbs(block: &Block, hibit: u32) -> [u32; 5] {
+    [
+        le32(&block[0..]) & MASK,
+        (le32(&block[3..]) >> 2) & MASK,
+        (le32(&block[6..]) >> 4) & MASK,
+        (le32(&block[9..]) >> 6) & MASK,
+        (le32(&block[12..]) >> 8) | hibit,
+    ]
+}
+
+// a * b mod 2^130 - 5, only partially reduced.
+#[inline(always)]
+#[allow(clippy::cast_possible_truncation)]
+pub fn mul(a: &[u32; 5], b: &[u32; 5]) -> [u32; 5] {
+    let [a0, a1, a2, a3, a4] = a.map(u64::from);
+    let [b0, b1, b2, b3, b4] = b.map(u64::from);
+    let (s1, s2, s3, s4) = (b1 * 5, b2 * 5, b3 * 5, b4 * 5);
+    let d = [
+        a0 * b0 + a1 * s4 + a2 * s3 + a3 * s2 + a4 * s1,
+        a0 * b1 + a1 * b0 + a2 * s4 + a3 * s3 + a4 * s2,
+        a0 * b2 + a1 * b1 + a2 * b0 + a3 * s4 + a4 * s3,
+        a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * s4,
+        a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0,
+    ];
+    carry(d)
+}
+
+#[inline(always)]
+#[allow(clippy::cast_possible_truncation)]
+pub const fn carry(mut d: [u64; 5]) -> [u32; 5] {
+    let mut c = d[0] >> 26;
+    d[0] &= MASK as u64;
+    d[1] += c;
+    c = d[1] >> 26;
+    d[1] &= MASK as u64;
+    d[2] += c;
+    c = d[2] >> 26;
+    d[2] &= MASK as u64;
+    d[3] += c;
+    c = d[3] >> 26;
+    d[3] &= MASK as u64;
+    d[4] += c;
+    c = d[4] >> 26;
+    d[4] &= MASK as u64;
+    d[0] += c * 5;
+    c = d[0] >> 26;
+    d[0] &= MASK as u64;
+    d[1] += c;
+    [
+        d[0] as u32,
+        d[1] as u32,
+        d[2] as u32,
+        d[3] as u32,
+        d[4] as u32,
+    ]
+}
+
+pub struct State {
+    pub r: [u32; 5],
+    pub h: [u32; 5],
+    pad: [u32; 4],
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        self.r.erase();
+        self.h.erase();
+        self.pad.erase();
+    }
+}
+
+impl EraseOnDrop for State {}
+
+impl State {
+    pub fn new(key: &Key) -> Self {
+        // clamp r as the spec requires.
+        let r = [
+            le32(&key[0..]) & 0x03ff_ffff,
+            (le32(&key[3..]) >> 2) & 0x03ff_ff03,
+            (le32(&key[6..]) >> 4) & 0x03ff_c0ff,
+            (le32(&key[9..]) >> 6) & 0x03f0_3fff,
+            (le32(&key[12..]) >> 8) & 0x000f_ffff,
+        ];
+        let pad = [
+            le32(&key[16..]),
+            le32(&key[20..]),
+            le32(&key[24..]),
+            le32(&key[28..]),
+        ];
+        Self { r, h: [0; 5], pad }
+    }
+
+    #[inline(always)]
+    pub fn block(&mut self, block: &Block, hibit: u32) {
+        let m = limbs(block, hibit);
+        for (h, m) in self.h.iter_mut().zip(m) {
+            *h += m;
+        }
+        self.h = mul(&self.h, &self.r);
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn finalize(&self) -> Tag {
+        let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+        // fully carry h.
+        let mut c = h1 >> 26;
+        h1 &= MASK;
+        h2 += c;
+        c = h2 >> 26;
+        h2 &= MASK;
+        h3 += c;
+        c = h3 >> 26;
+        h3 &= MASK;
+        h4 += c;
+        c = h4 >> 26;
+        h4 &= MASK;
+        h0 += c * 5;
+        c = h0 >> 26;
+        h0 &= MASK;
+        h1 += c;
+        // compute h + -p, and pick it if it didn't underflow.
+        let mut g0 = h0.wrapping_add(5);
+        c = g0 >> 26;
+        g0 &= MASK;
+        let mut g1 = h1.wrapping_add(c);
+        c = g1 >> 26;
+        g1 &= MASK;
+        let mut g2 = h2.wrapping_add(c);
+        c = g2 >> 26;
+        g2 &= MASK;
+        let mut g3 = h3.wrapping_add(c);
+        c = g3 >> 26;
+        g3 &= MASK;
+        let g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+        let mask = (g4 >> 31).wrapping_sub(1);
+        h0 = (h0 & !mask) | (g0 & mask);
+        h1 = (h1 & !mask) | (g1 & mask);
+        h2 = (h2 & !mask) | (g2 & mask);
+        h3 = (h3 & !mask) | (g3 & mask);
+        h4 = (h4 & !mask) | (g4 & mask);
+        // h mod 2^128, plus the pad.
+        let words = [
+            h0 | (h1 << 26),
+            (h1 >> 6) | (h2 << 20),
+            (h2 >> 12) | (h3 << 14),
+            (h3 >> 18) | (h4 << 8),
+        ];
+        let mut tag = [0; 16];
+        let mut f = 0;
+        for ((out, word), pad) in tag.chunks_mut(4).zip(words).zip(self.pad) {
+            f = u64::from(word) + u64::from(pad) + (f >> 32);
+            out.copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        tag
+    }
+}
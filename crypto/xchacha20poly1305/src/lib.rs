@@ -0,0 +1,161 @@
+#![no_std]
+#![deny(
+    dead_code,
+    deprecated,
+    future_incompatible,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    nonstandard_style,
+    rust_2018_idioms,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused,
+    clippy::all,
+    clippy::pedantic,
+    clippy::nursery
+)]
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+//! `XChaCha20-Poly1305`, as described in `draft-irtf-cfrg-xchacha`: the AEAD
+//! construction from RFC 8439, with `XChaCha20` in place of `ChaCha20`.
+
+use crypto_common::{
+    constant_time::ConstantTimeEq,
+    erase::{Erase, EraseOnDrop},
+};
+use poly1305::Poly1305;
+use xchacha20::XChaCha20;
+
+pub use poly1305::Tag;
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 24;
+pub const TAG_SIZE: usize = poly1305::TAG_SIZE;
+
+pub type Key = [u8; KEY_SIZE];
+pub type Nonce = [u8; NONCE_SIZE];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Error;
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("xchacha20poly1305: authentication failed")
+    }
+}
+
+pub struct XChaCha20Poly1305 {
+    key: Key,
+}
+
+impl XChaCha20Poly1305 {
+    #[must_use]
+    pub const fn new(key: &Key) -> Self {
+        Self { key: *key }
+    }
+
+    // the first keystream block provides the one-time Poly1305 key. the
+    // message is encrypted starting from the second block.
+    fn init(&self, nonce: &Nonce) -> (XChaCha20, Poly1305) {
+        let mut cipher = XChaCha20::new(&self.key, nonce);
+        let mut block = [0; 64];
+        cipher.apply_keystream(&mut block);
+        let mac = Poly1305::new(block[..poly1305::KEY_SIZE].try_into().unwrap());
+        block.erase();
+        (cipher, mac)
+    }
+
+    fn tag(mut mac: Poly1305, ad: &[u8], ct: &[u8]) -> Tag {
+        mac.update_padded(ad);
+        mac.update_padded(ct);
+        mac.update(&(ad.len() as u64).to_le_bytes());
+        mac.update(&(ct.len() as u64).to_le_bytes());
+        mac.finalize()
+    }
+
+    /// Encrypts `buf` in place and returns the tag authenticating it along
+    /// with `ad`. A nonce must never be reused with the same key.
+    ///
+    /// # Panics
+    /// If `buf` is larger than the keystream (256 GiB).
+    pub fn encrypt_in_place_detached(&self, nonce: &Nonce, ad: &[u8], buf: &mut [u8]) -> Tag {
+        let (mut cipher, mac) = self.init(nonce);
+        cipher.apply_keystream(buf);
+        Self::tag(mac, ad, buf)
+    }
+
+    /// Checks `tag` against `buf` and `ad`, and only then decrypts `buf` in
+    /// place. On failure, `buf` is left untouched.
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &Nonce,
+        ad: &[u8],
+        buf: &mut [u8],
+        tag: &Tag,
+    ) -> Result<(), Error> {
+        let (mut cipher, mac) = self.init(nonce);
+        if Self::tag(mac, ad, buf).ct_eq(tag).to_u8() != 1 {
+            return Err(Error);
+        }
+        cipher.apply_keystream(buf);
+        Ok(())
+    }
+}
+
+impl Drop for XChaCha20Poly1305 {
+    fn drop(&mut self) {
+        self.key.erase();
+    }
+}
+
+impl EraseOnDrop for XChaCha20Poly1305 {}
+
+impl core::fmt::Debug for XChaCha20Poly1305 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("XChaCha20Poly1305")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex<const N: usize>(s: &str) -> [u8; N] {
+        let mut out = [0; N];
+        for (i, b) in out.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    // draft-irtf-cfrg-xchacha-03, appendix A.3.1.
+    #[test]
+    fn test_draft_vector() {
+        let key = from_hex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+        let nonce = from_hex("404142434445464748494a4b4c4d4e4f5051525354555657");
+        let ad: [u8; 12] = from_hex("50515253c0c1c2c3c4c5c6c7");
+        let pt = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                   tip for the future, sunscreen would be it.";
+        let ct: [u8; 114] = from_hex(
+            "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa644\
+             0bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e\
+             3f3fac2bc369488f76b2383565d3fff921f9664c97637da9768812f615c68b13b52e",
+        );
+        let aead = XChaCha20Poly1305::new(&key);
+        let mut buf = *pt;
+        let tag = aead.encrypt_in_place_detached(&nonce, &ad, &mut buf);
+        assert_eq!(buf, ct);
+        assert_eq!(tag, from_hex::<16>("c0875924c1c7987947deafd8780acf49"));
+        aead.decrypt_in_place_detached(&nonce, &ad, &mut buf, &tag)
+            .unwrap();
+        assert_eq!(&buf, pt);
+        buf[0] ^= 1;
+        assert!(aead
+            .decrypt_in_place_detached(&nonce, &ad, &mut buf, &tag)
+            .is_err());
+        buf[0] ^= 1;
+        assert!(aead
+            .decrypt_in_place_detached(&nonce, b"", &mut buf, &tag)
+            .is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! The LZ4 block format.
+//!
+//! A block is a series of sequences, each made of a run of literals followed by
+//! a match that copies earlier output:
+//!
+//! ```text
+//! sequence := token || literal length? || literals || offset || match length?
+//! ```
+//!
+//! The high nibble of the token is the number of literals and the low nibble
+//! the length of the match, minus 4. A nibble of 15 is followed by more bytes
+//! that are added to it, up to and including the first that isn't 255. The
+//! offset counts back from the current position, as a 16-bit little-endian
+//! integer. The last sequence has no match, and the last 5 bytes of a block are
+//! always literals.
+//!
+//! Only the block format is implemented; the frame format that wraps blocks in
+//! a header and checksums is not.
+
+#![no_std]
+#![deny(
+    dead_code,
+    deprecated,
+    future_incompatible,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    nonstandard_style,
+    rust_2018_idioms,
+    trivial_casts,
+    trivial_numeric_casts,
+    unused,
+    clippy::all,
+    clippy::pedantic,
+    clippy::nursery
+)]
+
+use core::fmt::Display;
+
+const MIN_MATCH: usize = 4;
+const LAST_LITERALS: usize = 5;
+// a match has to start at least this far from the end of the block.
+const MF_LIMIT: usize = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+const HASH_LOG: u32 = 12;
+// after this many misses in a row, the compressor starts skipping ahead, which
+// keeps incompressible input fast.
+const SKIP_TRIGGER: usize = 6;
+
+/// The error returned when a block is malformed, or doesn't fit in the output
+/// buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("malformed LZ4 block")
+    }
+}
+
+/// The largest a block can get when compressing `len` bytes, which happens
+/// when nothing in them matches.
+#[must_use]
+pub const fn compress_bound(len: usize) -> usize {
+    len + len / 255 + 16
+}
+
+/// Compresses `input` into `output`, and returns the length of the block.
+///
+/// # Panics
+///
+/// Panics if `output` is shorter than `compress_bound(input.len())`.
+#[must_use]
+pub fn compress(input: &[u8], output: &mut [u8]) -> usize {
+    assert!(
+        output.len() >= compress_bound(input.len()),
+        "output buffer too small"
+    );
+    let mut table = [0u32; 1 << HASH_LOG];
+    let mut out = 0;
+    let mut anchor = 0;
+    let mut pos = 0;
+    if input.len() > MF_LIMIT {
+        let limit = input.len() - MF_LIMIT;
+        let end = input.len() - LAST_LITERALS;
+        let mut misses = 0usize;
+        while pos < limit {
+            let word = read_u32(input, pos);
+            let slot = &mut table[hash(word)];
+            let candidate = *slot as usize;
+            // positions past 4 GiB wrap around, and the table starts out zeroed,
+            // so a candidate may be bogus. comparing the bytes takes care of
+            // that.
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                *slot = pos as u32;
+            }
+            if candidate >= pos
+                || pos - candidate > MAX_OFFSET
+                || read_u32(input, candidate) != word
+            {
+                misses += 1;
+                pos += 1 + (misses >> SKIP_TRIGGER);
+                continue;
+            }
+            let mut len = MIN_MATCH;
+            while pos + len < end && input[candidate + len] == input[pos + len] {
+                len += 1;
+            }
+            out = sequence(
+                output,
+                out,
+                &input[anchor..pos],
+                Some((pos - candidate, len)),
+            );
+            pos += len;
+            anchor = pos;
+            misses = 0;
+        }
+    }
+    sequence(output, out, &input[anchor..], None)
+}
+
+/// Decompresses `input` into `output`, and returns the number of bytes
+/// written.
+///
+/// # Errors
+///
+/// Returns an error if the block is malformed, or if `output` is too short to
+/// hold the result.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, Error> {
+    let mut i = 0;
+    let mut out = 0;
+    loop {
+        let token = *input.get(i).ok_or(Error)?;
+        i += 1;
+        let mut len = usize::from(token >> 4);
+        if len == 15 {
+            len = len.checked_add(read_len(input, &mut i)?).ok_or(Error)?;
+        }
+        let literals = input
+            .get(i..i.checked_add(len).ok_or(Error)?)
+            .ok_or(Error)?;
+        output
+            .get_mut(out..out + len)
+            .ok_or(Error)?
+            .copy_from_slice(literals);
+        i += len;
+        out += len;
+        if i == input.len() {
+            return Ok(out);
+        }
+        let offset = input.get(i..i + 2).ok_or(Error)?;
+        let offset = usize::from(u16::from_le_bytes([offset[0], offset[1]]));
+        i += 2;
+        if offset == 0 || offset > out {
+            return Err(Error);
+        }
+        let mut len = usize::from(token & 15);
+        if len == 15 {
+            len = len.checked_add(read_len(input, &mut i)?).ok_or(Error)?;
+        }
+        len += MIN_MATCH;
+        if len > output.len() - out {
+            return Err(Error);
+        }
+        let start = out - offset;
+        if offset >= len {
+            output.copy_within(start..start + len, out);
+        } else {
+            // the match overlaps what it produces, which repeats the last
+            // `offset` bytes. that has to go one byte at a time.
+            for k in 0..len {
+                output[out + k] = output[start + k];
+            }
+        }
+        out += len;
+    }
+}
+
+const fn read_u32(input: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([input[pos], input[pos + 1], input[pos + 2], input[pos + 3]])
+}
+
+const fn hash(word: u32) -> usize {
+    (word.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+fn read_len(input: &[u8], i: &mut usize) -> Result<usize, Error> {
+    let mut len = 0usize;
+    loop {
+        let byte = *input.get(*i).ok_or(Error)?;
+        *i += 1;
+        len = len.checked_add(usize::from(byte)).ok_or(Error)?;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+const fn write_len(output: &mut [u8], mut out: usize, mut len: usize) -> usize {
+    while len >= 255 {
+        output[out] = 255;
+        out += 1;
+        len -= 255;
+    }
+    #[allow(clippy::cast_possible_truncation)] // less than 255 by now
+    {
+        output[out] = len as u8;
+    }
+    out + 1
+}
+
+// writes a sequence at `out`, and returns where the next one goes. `m` is the
+// offset and length of the match, which only the last sequence goes without.
+#[allow(clippy::cast_possible_truncation)]
+fn sequence(
+    output: &mut [u8],
+    mut out: usize,
+    literals: &[u8],
+    m: Option<(usize, usize)>,
+) -> usize {
+    let token = out;
+    out += 1;
+    output[token] = (literals.len().min(15) as u8) << 4;
+    if literals.len() >= 15 {
+        out = write_len(output, out, literals.len() - 15);
+    }
+    output[out..out + literals.len()].copy_from_slice(literals);
+    out += literals.len();
+    if let Some((offset, len)) = m {
+        output[out..out + 2].copy_from_slice(&(offset as u16).to_le_bytes());
+        out += 2;
+        let len = len - MIN_MATCH;
+        output[token] |= len.min(15) as u8;
+        if len >= 15 {
+            out = write_len(output, out, len - 15);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEN: usize = 16_000;
+
+    fn roundtrip(input: &[u8]) -> usize {
+        let mut block = [0; compress_bound(LEN)];
+        let mut output = [0; LEN];
+        let len = compress(input, &mut block);
+        assert!(len <= compress_bound(input.len()));
+        assert_eq!(decompress(&block[..len], &mut output), Ok(input.len()));
+        assert_eq!(&output[..input.len()], input);
+        len
+    }
+
+    #[test]
+    #[allow(clippy::cast_possible_truncation)]
+    fn test_roundtrip() {
+        let mut input = [0; LEN];
+        // an xorshift generator, for something that doesn't compress.
+        let mut x = 0x2545_f491_4f6c_dd1d_u64;
+        for b in &mut input {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *b = x as u8;
+        }
+        assert!(roundtrip(&input) > LEN);
+        for len in [0, 1, 5, 12, 13, 17, 255, 300] {
+            roundtrip(&input[..len]);
+        }
+        let text = b"the quick brown fox jumps over the lazy dog. ";
+        for (i, b) in input.iter_mut().enumerate() {
+            *b = text[i % text.len()];
+        }
+        assert!(roundtrip(&input) < LEN / 50);
+        roundtrip(&[0; LEN]);
+    }
+
+    #[test]
+    fn test_decompress() {
+        // "abc", then a match of 9 at offset 3, then 5 literals.
+        let block = b"\x35abc\x03\x00\x50abcab";
+        let mut output = [0; 32];
+        assert_eq!(decompress(block, &mut output), Ok(17));
+        assert_eq!(&output[..17], b"abcabcabcabcabcab");
+        assert_eq!(decompress(block, &mut output[..16]), Err(Error));
+        assert_eq!(decompress(b"\x30abc\x04\x00", &mut output), Err(Error));
+        assert_eq!(decompress(b"\x30abc\x00\x00", &mut output), Err(Error));
+        assert_eq!(decompress(b"\x30ab", &mut output), Err(Error));
+        assert_eq!(decompress(b"\xf0\xff", &mut output), Err(Error));
+        assert_eq!(decompress(b"", &mut output), Err(Error));
+    }
+}
@@ -0,0 +1,190 @@
+use std::fs::Metadata;
+
+/// Returns the metadata of whatever standard input is redirected from, which is
+/// only useful if it is a regular file.
+#[cfg(unix)]
+#[must_use]
+pub fn stdin_metadata() -> Option<Metadata> {
+    use std::os::fd::AsFd;
+
+    let fd = std::io::stdin().as_fd().try_clone_to_owned().ok()?;
+    std::fs::File::from(fd).metadata().ok()
+}
+
+#[cfg(windows)]
+#[must_use]
+pub fn stdin_metadata() -> Option<Metadata> {
+    use std::os::windows::io::AsHandle;
+
+    let handle = std::io::stdin().as_handle().try_clone_to_owned().ok()?;
+    std::fs::File::from(handle).metadata().ok()
+}
+
+#[cfg(not(any(windows, unix)))]
+#[must_use]
+#[allow(clippy::missing_const_for_fn)]
+pub fn stdin_metadata() -> Option<Metadata> {
+    None
+}
+
+/// Returns the permission bits, on platforms that have them.
+#[cfg(unix)]
+#[must_use]
+pub fn mode(metadata: &Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+#[must_use]
+#[allow(clippy::missing_const_for_fn)]
+pub fn mode(_metadata: &Metadata) -> Option<u32> {
+    None
+}
+
+/// Sets the permission bits, on platforms that have them.
+///
+/// # Errors
+///
+/// This function will return an error if the permissions could not be changed.
+#[cfg(unix)]
+pub fn set_mode(file: &std::fs::File, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(std::fs::Permissions::from_mode(mode))
+}
+
+/// Sets the permission bits, on platforms that have them.
+///
+/// # Errors
+///
+/// This function never fails on platforms without permission bits.
+#[cfg(not(unix))]
+#[allow(clippy::missing_const_for_fn)]
+pub fn set_mode(_file: &std::fs::File, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Sets the permission bits of whatever is at `path`, on platforms that have
+/// them. Symlinks are followed.
+///
+/// # Errors
+///
+/// This function will return an error if the permissions could not be changed.
+#[cfg(unix)]
+pub fn set_path_mode(path: &std::path::Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+/// Sets the permission bits of whatever is at `path`, on platforms that have
+/// them.
+///
+/// # Errors
+///
+/// This function never fails on platforms without permission bits.
+#[cfg(not(unix))]
+#[allow(clippy::missing_const_for_fn)]
+pub fn set_path_mode(_path: &std::path::Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Creates a new file at `path` that only the current user can read and write,
+/// on platforms that have permission bits.
+///
+/// # Errors
+///
+/// This function will return an error if the file could not be created, or if
+/// it already exists.
+#[cfg(unix)]
+pub fn create_private(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+}
+
+/// Creates a new file at `path` that only the current user can read and write,
+/// on platforms that have permission bits.
+///
+/// # Errors
+///
+/// This function will return an error if the file could not be created, or if
+/// it already exists.
+#[cfg(not(unix))]
+pub fn create_private(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(path)
+}
+
+/// Moves the file at `from` to `to`, unless something is already there.
+///
+/// This is done by linking it to its new name and then removing the old one,
+/// which can't replace anything. On file systems without hard links, it falls
+/// back to checking first, which can.
+///
+/// # Errors
+///
+/// This function will return an error with [`std::io::ErrorKind::AlreadyExists`]
+/// if `to` already exists, or any other error if the file could not be moved.
+pub fn rename_noreplace(from: &std::path::Path, to: &std::path::Path) -> std::io::Result<()> {
+    match std::fs::hard_link(from, to) {
+        Ok(()) => std::fs::remove_file(from),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Err(e),
+        Err(_) if std::fs::symlink_metadata(to).is_ok() => {
+            Err(std::io::ErrorKind::AlreadyExists.into())
+        }
+        Err(_) => std::fs::rename(from, to),
+    }
+}
+
+/// Creates a symlink at `path` that points to `target`. On Windows, where
+/// symlinks to files and directories differ, the kind is picked by looking at
+/// what `target` is, which is a file if it doesn't exist.
+///
+/// # Errors
+///
+/// This function will return an error if the symlink could not be created, or
+/// if the platform doesn't have symlinks.
+#[cfg(unix)]
+pub fn symlink(target: &str, path: &std::path::Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+/// Creates a symlink at `path` that points to `target`. On Windows, where
+/// symlinks to files and directories differ, the kind is picked by looking at
+/// what `target` is, which is a file if it doesn't exist.
+///
+/// # Errors
+///
+/// This function will return an error if the symlink could not be created, or
+/// if the platform doesn't have symlinks.
+#[cfg(windows)]
+pub fn symlink(target: &str, path: &std::path::Path) -> std::io::Result<()> {
+    let target = target.replace('/', "\\");
+    let is_dir = path.parent().is_some_and(|dir| dir.join(&target).is_dir());
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, path)
+    } else {
+        std::os::windows::fs::symlink_file(target, path)
+    }
+}
+
+/// Creates a symlink at `path` that points to `target`.
+///
+/// # Errors
+///
+/// This function always fails, since this platform doesn't have symlinks.
+#[cfg(not(any(windows, unix)))]
+pub fn symlink(_target: &str, _path: &std::path::Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
@@ -16,7 +16,9 @@
 )]
 
 pub mod env;
+pub mod fs;
 pub mod password;
 mod preflight;
 pub use preflight::preflight;
+pub mod process;
 pub mod tty;
@@ -0,0 +1,19 @@
+use std::process::Command;
+
+/// Returns a command that runs `command` with the system shell.
+#[cfg(windows)]
+#[must_use]
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+/// Returns a command that runs `command` with the system shell.
+#[cfg(not(windows))]
+#[must_use]
+pub fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
@@ -0,0 +1,687 @@
+use crate::{error::Error, util::sync_dir};
+use rand_core::RngCore;
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+// directories are sent as a stream of entries, in an order where every
+// directory comes before what it contains:
+//
+// archive := MAGIC || entry* || END
+// entry    := kind || Len16(len(path)) || path || Len32(mode) || body
+// body     := Len(size) || data            for a FILE
+//           | Len16(len(target)) || target for a SYMLINK
+//           | nothing                      for a DIR
+//
+// paths are relative, use '/' as the separator, and all start with the name of
+// the directory that was copied. symlinks are stored as they are, and never
+// followed on either side.
+pub const CONTENT_TYPE: &str = "application/x-klip-archive";
+const MAGIC: &[u8; 5] = b"KLAR\x01";
+const END: u8 = 0;
+const DIR: u8 = 1;
+const FILE: u8 = 2;
+const SYMLINK: u8 = 3;
+
+#[derive(Debug)]
+enum Kind {
+    Dir,
+    File,
+    Symlink,
+}
+
+#[derive(Debug)]
+struct Entry {
+    kind: Kind,
+    path: PathBuf,
+    name: String,
+    mode: u32,
+}
+
+const fn default_mode(kind: &Kind) -> u32 {
+    match kind {
+        Kind::Dir => 0o755,
+        Kind::File => 0o644,
+        Kind::Symlink => 0o777,
+    }
+}
+
+// the components of `name`, if it is a relative path that stays where it is
+// put.
+fn components(name: &str) -> Option<Vec<&str>> {
+    let parts = name.split('/').collect::<Vec<_>>();
+    let safe = parts.iter().all(|part| {
+        let mut components = Path::new(part).components();
+        matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) && !part.contains(['\\', '\0'])
+    });
+    safe.then_some(parts)
+}
+
+fn to_name(path: &Path) -> Result<&str, Error> {
+    path.to_str()
+        .ok_or_else(|| Error::InvalidFileName(path.to_string_lossy().into_owned()))
+}
+
+fn walk(root: &Path, name: &str, entries: &mut Vec<Entry>) -> Result<(), Error> {
+    let metadata = fs::symlink_metadata(root)?;
+    let kind = if metadata.is_dir() {
+        Kind::Dir
+    } else if metadata.is_file() {
+        Kind::File
+    } else if metadata.is_symlink() {
+        Kind::Symlink
+    } else {
+        eprintln!(
+            "skipping '{}': not a file, directory or symlink",
+            root.display()
+        );
+        return Ok(());
+    };
+    let mode = platform::fs::mode(&metadata).unwrap_or_else(|| default_mode(&kind));
+    let is_dir = matches!(kind, Kind::Dir);
+    entries.push(Entry {
+        kind,
+        path: root.to_path_buf(),
+        name: name.to_owned(),
+        mode,
+    });
+    if is_dir {
+        let mut children = fs::read_dir(root)?
+            .map(|child| child.map(|child| child.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        children.sort();
+        for child in children {
+            let path = root.join(&child);
+            let child = to_name(Path::new(&child))?;
+            walk(&path, &format!("{name}/{child}"), entries)?;
+        }
+    }
+    Ok(())
+}
+
+// produces the archive as it is read, one entry at a time, so that file
+// content never has to be held in memory.
+#[derive(Debug)]
+pub struct Packer {
+    entries: VecDeque<Entry>,
+    buf: Vec<u8>,
+    pos: usize,
+    file: Option<(File, u64)>,
+    done: bool,
+}
+
+impl Packer {
+    // returns the packer and the name of the root entry, which is `name` if
+    // one is given.
+    pub fn new(root: &Path, name: Option<&str>) -> Result<(Self, String), Error> {
+        let canonical = fs::canonicalize(root)?;
+        // the root itself may well be a symlink, in which case it is sent as
+        // one, under its own name.
+        let name = match name {
+            Some(name) if components(name).is_some_and(|parts| parts.len() == 1) => name,
+            Some(name) => return Err(Error::InvalidFileName(name.to_owned())),
+            None => {
+                let name = root
+                    .file_name()
+                    .or_else(|| canonical.file_name())
+                    .ok_or_else(|| Error::InvalidFileName(root.display().to_string()))?;
+                to_name(Path::new(name))?
+            }
+        };
+        let name = name.to_owned();
+        let mut entries = Vec::new();
+        walk(root, &name, &mut entries)?;
+        let packer = Self {
+            entries: entries.into(),
+            buf: MAGIC.to_vec(),
+            pos: 0,
+            file: None,
+            done: false,
+        };
+        Ok((packer, name))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn next_entry(&mut self) -> io::Result<()> {
+        self.buf.clear();
+        self.pos = 0;
+        let Some(entry) = self.entries.pop_front() else {
+            self.buf.push(END);
+            self.done = true;
+            return Ok(());
+        };
+        let too_long = || io::Error::new(io::ErrorKind::InvalidInput, "path too long");
+        let kind = match entry.kind {
+            Kind::Dir => DIR,
+            Kind::File => FILE,
+            Kind::Symlink => SYMLINK,
+        };
+        self.buf.push(kind);
+        let name = u16::try_from(entry.name.len()).map_err(|_| too_long())?;
+        self.buf.extend_from_slice(&name.to_le_bytes());
+        self.buf.extend_from_slice(entry.name.as_bytes());
+        self.buf.extend_from_slice(&entry.mode.to_le_bytes());
+        match entry.kind {
+            Kind::Dir => {}
+            Kind::File => {
+                let file = File::open(&entry.path)?;
+                // a file that grows while it is being sent is cut short, and
+                // one that shrinks fails the copy.
+                let size = file.metadata()?.len();
+                self.buf.extend_from_slice(&size.to_le_bytes());
+                self.file = Some((file, size));
+            }
+            Kind::Symlink => {
+                let target = fs::read_link(&entry.path)?;
+                let target = target.to_str().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "symlink target is not UTF-8")
+                })?;
+                let len = u16::try_from(target.len()).map_err(|_| too_long())?;
+                self.buf.extend_from_slice(&len.to_le_bytes());
+                self.buf.extend_from_slice(target.as_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for Packer {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.buf.len() {
+                let n = out.len().min(self.buf.len() - self.pos);
+                out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            if let Some((file, remaining)) = &mut self.file {
+                if *remaining > 0 {
+                    let max = out
+                        .len()
+                        .min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                    let n = file.read(&mut out[..max])?;
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "a file shrank while it was being sent",
+                        ));
+                    }
+                    *remaining -= n as u64;
+                    return Ok(n);
+                }
+                self.file = None;
+            }
+            if self.done {
+                return Ok(0);
+            }
+            self.next_entry()?;
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Magic,
+    Entry,
+    File {
+        file: BufWriter<File>,
+        remaining: u64,
+        mode: u32,
+    },
+    Done,
+}
+
+enum Parsed {
+    Need(usize),
+    End,
+    Dir(String, u32),
+    File(String, u32, u64),
+    Symlink(String, String),
+}
+
+fn parse(buf: &[u8]) -> Result<Parsed, Error> {
+    fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        let (head, rest) = buf.split_at_checked(len)?;
+        *buf = rest;
+        Some(head)
+    }
+    fn string(bytes: &[u8]) -> Result<String, Error> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::Corrupt)
+    }
+    let full = buf.len();
+    let mut buf = buf;
+    let need = |want: usize| Ok(Parsed::Need(want - full));
+    let Some(&[kind]) = take(&mut buf, 1) else {
+        return Ok(Parsed::Need(1));
+    };
+    if kind == END {
+        return Ok(Parsed::End);
+    }
+    let Some(len) = take(&mut buf, 2) else {
+        return need(3);
+    };
+    let len = usize::from(u16::from_le_bytes([len[0], len[1]]));
+    let fixed = 3 + len + 4;
+    let (Some(path), Some(mode)) = (take(&mut buf, len), take(&mut buf, 4)) else {
+        return need(fixed);
+    };
+    let path = string(path)?;
+    let mode = u32::from_le_bytes([mode[0], mode[1], mode[2], mode[3]]);
+    match kind {
+        DIR => Ok(Parsed::Dir(path, mode)),
+        FILE => {
+            let Some(size) = take(&mut buf, 8) else {
+                return need(fixed + 8);
+            };
+            let size = u64::from_le_bytes(size.try_into().expect("8 bytes were taken"));
+            Ok(Parsed::File(path, mode, size))
+        }
+        SYMLINK => {
+            let Some(len) = take(&mut buf, 2) else {
+                return need(fixed + 2);
+            };
+            let len = usize::from(u16::from_le_bytes([len[0], len[1]]));
+            let Some(target) = take(&mut buf, len) else {
+                return need(fixed + 2 + len);
+            };
+            Ok(Parsed::Symlink(path, string(target)?))
+        }
+        _ => Err(Error::Corrupt),
+    }
+}
+
+// extracts an archive into a temporary directory next to its destination, as
+// it comes in. only once the signature has been checked is the result moved
+// into place.
+//
+// nothing is ever written outside of the temporary directory: every entry has
+// to be inside the directory of an entry that came before it, and only
+// directories that were created by the archive itself count. a symlink can't
+// be used to get out, since it is never a directory in that sense.
+#[derive(Debug)]
+pub struct Unpacker {
+    tmp: PathBuf,
+    dest: PathBuf,
+    force: bool,
+    pending: Vec<u8>,
+    state: State,
+    root: Option<String>,
+    dirs: HashSet<String>,
+    dir_modes: Vec<(PathBuf, u32)>,
+    // set if the temporary directory holds something that isn't ours to remove.
+    keep: bool,
+}
+
+impl Unpacker {
+    // `name` is the one the archive was sent under, which is checked for
+    // upfront. where it really ends up is only known once the archive is in.
+    pub fn new(dest: &Path, name: Option<&str>, force: bool) -> Result<Self, Error> {
+        if let Some(name) =
+            name.filter(|name| components(name).is_some_and(|parts| parts.len() == 1))
+        {
+            let path = dest.join(name);
+            if !force && fs::symlink_metadata(&path).is_ok() {
+                return Err(Error::Exists(path));
+            }
+        }
+        let tmp = dest.join(format!(".klip-{:016x}.tmp", rand_core::OsRng.next_u64()));
+        fs::create_dir(&tmp)?;
+        Ok(Self {
+            tmp,
+            dest: dest.to_path_buf(),
+            force,
+            pending: Vec::new(),
+            state: State::Magic,
+            root: None,
+            dirs: HashSet::new(),
+            dir_modes: Vec::new(),
+            keep: false,
+        })
+    }
+
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        loop {
+            let need = match &mut self.state {
+                State::File {
+                    file, remaining, ..
+                } => {
+                    if data.is_empty() {
+                        return Ok(());
+                    }
+                    let n = data
+                        .len()
+                        .min(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                    file.write_all(&data[..n])?;
+                    data = &data[n..];
+                    *remaining -= n as u64;
+                    if *remaining == 0 {
+                        self.finish_file()?;
+                    }
+                    continue;
+                }
+                State::Magic if self.pending.len() == MAGIC.len() => {
+                    if self.pending != MAGIC {
+                        return Err(Error::Corrupt);
+                    }
+                    self.pending.clear();
+                    self.state = State::Entry;
+                    continue;
+                }
+                State::Magic => MAGIC.len() - self.pending.len(),
+                State::Entry => match parse(&self.pending)? {
+                    Parsed::Need(need) => need,
+                    parsed => {
+                        self.pending.clear();
+                        self.entry(parsed)?;
+                        continue;
+                    }
+                },
+                // nothing may follow the end of the archive.
+                State::Done if data.is_empty() => return Ok(()),
+                State::Done => return Err(Error::Corrupt),
+            };
+            if data.is_empty() {
+                return Ok(());
+            }
+            let (head, rest) = data.split_at(need.min(data.len()));
+            self.pending.extend_from_slice(head);
+            data = rest;
+        }
+    }
+
+    // checks that `name` is safe to create, and returns where it goes.
+    fn path(&mut self, name: &str) -> Result<PathBuf, Error> {
+        let unsafe_path = || Error::UnsafePath(name.to_owned());
+        let parts = components(name).ok_or_else(unsafe_path)?;
+        match (&self.root, parts.as_slice()) {
+            (None, [root]) => self.root = Some((*root).to_owned()),
+            (Some(_), [_, .., _]) => {
+                let parent = &name[..name.rfind('/').expect("there are several components")];
+                if !self.dirs.contains(parent) {
+                    return Err(unsafe_path());
+                }
+            }
+            _ => return Err(unsafe_path()),
+        }
+        Ok(parts
+            .iter()
+            .fold(self.tmp.clone(), |path, part| path.join(part)))
+    }
+
+    fn entry(&mut self, parsed: Parsed) -> Result<(), Error> {
+        match parsed {
+            Parsed::Need(_) => unreachable!(),
+            Parsed::End => {
+                if self.root.is_none() {
+                    return Err(Error::Corrupt);
+                }
+                self.state = State::Done;
+            }
+            Parsed::Dir(name, mode) => {
+                let path = self.path(&name)?;
+                fs::create_dir(&path)?;
+                // permissions that would keep us from filling the directory are
+                // only applied at the end.
+                self.dir_modes.push((path, mode));
+                self.dirs.insert(name);
+            }
+            Parsed::File(name, mode, size) => {
+                let path = self.path(&name)?;
+                let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+                self.state = State::File {
+                    file: BufWriter::new(file),
+                    remaining: size,
+                    mode,
+                };
+                if size == 0 {
+                    self.finish_file()?;
+                }
+            }
+            Parsed::Symlink(name, target) => {
+                let path = self.path(&name)?;
+                platform::fs::symlink(&target, &path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_file(&mut self) -> Result<(), Error> {
+        let State::File { file, mode, .. } = std::mem::replace(&mut self.state, State::Entry)
+        else {
+            unreachable!()
+        };
+        let file = file.into_inner().map_err(io::IntoInnerError::into_error)?;
+        // setuid and friends don't survive the trip.
+        platform::fs::set_mode(&file, mode & 0o777)?;
+        Ok(())
+    }
+
+    // moves the extracted directory into place, and returns where it went.
+    pub fn commit(mut self) -> Result<PathBuf, Error> {
+        if !matches!(self.state, State::Done) {
+            return Err(Error::Corrupt);
+        }
+        let root = self.root.take().expect("a finished archive has a root");
+        let path = self.dest.join(&root);
+        let exists = fs::symlink_metadata(&path).is_ok();
+        if exists && !self.force {
+            return Err(Error::Exists(path));
+        }
+        for (dir, mode) in self.dir_modes.drain(..).rev() {
+            platform::fs::set_path_mode(&dir, mode & 0o777)?;
+        }
+        // the old one is moved out of the way first, and removed along with
+        // the temporary directory. it is put back if the new one can't take
+        // its place, rather than going down with the rest.
+        let old = self.tmp.join(format!("{root}.old"));
+        if exists {
+            fs::rename(&path, &old)?;
+        }
+        if let Err(e) = fs::rename(self.tmp.join(&root), &path) {
+            if exists && fs::rename(&old, &path).is_err() {
+                self.keep = true;
+                eprintln!(
+                    "the old '{}' was left at '{}'",
+                    path.display(),
+                    old.display()
+                );
+            }
+            return Err(e.into());
+        }
+        sync_dir(&self.dest)?;
+        Ok(path)
+    }
+}
+
+impl Drop for Unpacker {
+    fn drop(&mut self) {
+        if !self.keep {
+            _ = fs::remove_dir_all(&self.tmp);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own under the system's temporary one, removed at the
+    // end of the test.
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new() -> io::Result<Self> {
+            let path = std::env::temp_dir()
+                .join(format!("klip-test-{:016x}", rand_core::OsRng.next_u64()));
+            fs::create_dir(&path)?;
+            Ok(Self(path))
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn entry(archive: &mut Vec<u8>, kind: u8, path: &str, body: &[u8]) {
+        archive.push(kind);
+        archive.extend_from_slice(&(path.len() as u16).to_le_bytes());
+        archive.extend_from_slice(path.as_bytes());
+        archive.extend_from_slice(&0o755u32.to_le_bytes());
+        match kind {
+            FILE => archive.extend_from_slice(&(body.len() as u64).to_le_bytes()),
+            SYMLINK => archive.extend_from_slice(&(body.len() as u16).to_le_bytes()),
+            _ => {}
+        }
+        archive.extend_from_slice(body);
+    }
+
+    fn archive(entries: &[(u8, &str, &[u8])]) -> Vec<u8> {
+        let mut archive = MAGIC.to_vec();
+        for &(kind, path, body) in entries {
+            entry(&mut archive, kind, path, body);
+        }
+        archive.push(END);
+        archive
+    }
+
+    fn unpack(entries: &[(u8, &str, &[u8])]) -> Result<(), Error> {
+        let scratch = Scratch::new()?;
+        let mut unpacker = Unpacker::new(&scratch.0, None, false)?;
+        unpacker.write_all(&archive(entries))?;
+        let root = unpacker.commit()?;
+        assert!(root.starts_with(&scratch.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hostile() {
+        let unsafe_path = |entries: &[(u8, &str, &[u8])]| {
+            assert!(
+                matches!(unpack(entries), Err(Error::UnsafePath(_))),
+                "{entries:?}"
+            );
+        };
+        unsafe_path(&[(DIR, "..", b"")]);
+        unsafe_path(&[(DIR, ".", b"")]);
+        unsafe_path(&[(DIR, "", b"")]);
+        unsafe_path(&[(FILE, "/etc/passwd", b"x")]);
+        unsafe_path(&[(DIR, "a", b""), (FILE, "a/../b", b"x")]);
+        unsafe_path(&[(DIR, "a", b""), (FILE, "a//b", b"x")]);
+        unsafe_path(&[(DIR, "a", b""), (FILE, "a/./b", b"x")]);
+        unsafe_path(&[(DIR, "a", b""), (FILE, "a/b\\..\\c", b"x")]);
+        // a second root, or the same one again.
+        unsafe_path(&[(DIR, "a", b""), (DIR, "b", b"")]);
+        unsafe_path(&[(DIR, "a", b""), (DIR, "a", b"")]);
+        // parents have to be directories that came before.
+        unsafe_path(&[(DIR, "a", b""), (FILE, "a/b/c", b"x")]);
+        unsafe_path(&[(FILE, "a", b"x"), (FILE, "a/b", b"x")]);
+        unsafe_path(&[(DIR, "a", b""), (FILE, "b/c", b"x")]);
+        // a symlink is never a directory to put things in, wherever it points.
+        unsafe_path(&[
+            (DIR, "a", b""),
+            (SYMLINK, "a/l", b"/tmp"),
+            (FILE, "a/l/x", b"x"),
+        ]);
+        unsafe_path(&[(DIR, "a", b""), (SYMLINK, "a/l", b"."), (DIR, "a/l/b", b"")]);
+        unsafe_path(&[(SYMLINK, "a", b"/tmp"), (FILE, "a/x", b"x")]);
+        // duplicate entries never replace what is already there.
+        assert!(unpack(&[(DIR, "a", b""), (FILE, "a/b", b"x"), (FILE, "a/b", b"y")]).is_err());
+        assert!(unpack(&[(DIR, "a", b""), (DIR, "a/b", b""), (SYMLINK, "a/b", b"/")]).is_err());
+        assert!(unpack(&[(DIR, "a", b""), (DIR, "a/b", b""), (FILE, "a/b/c", b"x")]).is_ok());
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let corrupt = |archive: &[u8]| {
+            let scratch = Scratch::new().expect("the scratch directory is created");
+            let mut unpacker =
+                Unpacker::new(&scratch.0, None, false).expect("the unpacker is created");
+            let result = unpacker
+                .write_all(archive)
+                .and_then(|()| unpacker.commit().map(drop));
+            assert!(matches!(result, Err(Error::Corrupt)), "{archive:?}");
+        };
+        corrupt(b"KLAR\x02");
+        corrupt(&[MAGIC.as_slice(), &[END]].concat());
+        corrupt(&[MAGIC.as_slice(), &[9, 1, 0, b'a', 0, 0, 0, 0]].concat());
+        corrupt(&[MAGIC.as_slice(), &[DIR, 1, 0, 0xff, 0, 0, 0, 0]].concat());
+        corrupt(&[&archive(&[(DIR, "a", b"")])[..], b"x"].concat());
+        // truncated archives are never done.
+        let full = archive(&[(DIR, "a", b""), (FILE, "a/b", b"xyz")]);
+        corrupt(&full[..full.len() - 1]);
+        corrupt(&full[..full.len() - 2]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let full = archive(&[(FILE, "a", b"xyz")]);
+        // everything up to the file's data, which parse doesn't look at.
+        let entry = &full[MAGIC.len()..full.len() - 4];
+        for len in 0..=entry.len() {
+            match parse(&entry[..len]) {
+                Ok(Parsed::Need(need)) => assert!(need > 0 && len + need <= entry.len()),
+                Ok(Parsed::File(name, mode, size)) => {
+                    assert_eq!((name.as_str(), mode, size), ("a", 0o755, 3));
+                    assert_eq!(len, entry.len());
+                }
+                _ => panic!("unexpected result for {len} bytes"),
+            }
+        }
+        assert!(matches!(parse(&[END]), Ok(Parsed::End)));
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<(), Error> {
+        let scratch = Scratch::new()?;
+        let src = scratch.0.join("src");
+        fs::create_dir_all(src.join("tree/sub/empty"))?;
+        fs::write(src.join("tree/a.txt"), b"hello")?;
+        fs::write(src.join("tree/sub/b.bin"), vec![7; 100_000])?;
+        fs::write(src.join("tree/sub/empty.txt"), b"")?;
+        #[cfg(unix)]
+        platform::fs::symlink("../a.txt", &src.join("tree/sub/link"))?;
+        let (mut packer, name) = Packer::new(&src.join("tree"), Some("copy"))?;
+        assert_eq!(name, "copy");
+        let mut bytes = Vec::new();
+        packer.read_to_end(&mut bytes)?;
+        let dest = scratch.0.join("dest");
+        fs::create_dir(&dest)?;
+        let mut unpacker = Unpacker::new(&dest, Some(&name), false)?;
+        // the archive comes in chunks that don't line up with anything.
+        for chunk in bytes.chunks(7) {
+            unpacker.write_all(chunk)?;
+        }
+        let root = unpacker.commit()?;
+        assert_eq!(root, dest.join("copy"));
+        assert_eq!(fs::read(root.join("a.txt"))?, b"hello");
+        assert_eq!(fs::read(root.join("sub/b.bin"))?, vec![7; 100_000]);
+        assert_eq!(fs::read(root.join("sub/empty.txt"))?, b"");
+        assert!(root.join("sub/empty").is_dir());
+        #[cfg(unix)]
+        assert_eq!(fs::read_link(root.join("sub/link"))?, Path::new("../a.txt"));
+        // nothing is left behind but the directory itself.
+        assert_eq!(fs::read_dir(&dest)?.count(), 1);
+        // an existing directory is only replaced with force, and is caught
+        // before anything is written.
+        assert!(matches!(
+            Unpacker::new(&dest, Some(&name), false),
+            Err(Error::Exists(_))
+        ));
+        let mut unpacker = Unpacker::new(&dest, Some(&name), true)?;
+        unpacker.write_all(&archive(&[(DIR, "copy", b""), (FILE, "copy/new", b"x")]))?;
+        let root = unpacker.commit()?;
+        assert_eq!(fs::read_dir(&root)?.count(), 1);
+        assert_eq!(fs::read(root.join("new"))?, b"x");
+        assert_eq!(fs::read_dir(&dest)?.count(), 1);
+        Ok(())
+    }
+}
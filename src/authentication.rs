@@ -9,29 +9,51 @@ fn new_blake2b(psk: [u8; 32], salt: u8) -> blake2b::State {
     p.to_state()
 }
 
-pub fn auth0(psk: [u8; 32], client_version: u8, r: &[u8]) -> blake2b::Hash {
+// `versions` is the range of versions the client speaks, or the only one for
+// version 1 clients.
+pub fn auth0(psk: [u8; 32], versions: &[u8], r: &[u8]) -> blake2b::Hash {
     let mut hf = new_blake2b(psk, 0);
-    hf.update(&[client_version]);
+    hf.update(versions);
     hf.update(r);
     hf.finalize()
 }
 
-pub fn auth1(psk: [u8; 32], client_version: u8, h0: &[u8], r2: &[u8]) -> blake2b::Hash {
+pub fn auth1(psk: [u8; 32], version: u8, h0: &[u8], r2: &[u8]) -> blake2b::Hash {
     let mut hf = new_blake2b(psk, 1);
-    hf.update(&[client_version]);
+    hf.update(&[version]);
     hf.update(r2);
     hf.update(h0);
     hf.finalize()
 }
 
-pub fn auth2get(psk: [u8; 32], h1: &[u8], opcode: u8) -> blake2b::Hash {
+pub fn auth2get(psk: [u8; 32], h1: &[u8], opcode: u8, slot: &[u8], index: &[u8]) -> blake2b::Hash {
     let mut hf = new_blake2b(psk, 2);
     hf.update(h1);
     hf.update(&[opcode]);
+    hf.update(slot);
+    hf.update(index);
     hf.finalize()
 }
 
-pub fn auth2store(
+pub fn auth2history(psk: [u8; 32], h1: &[u8], opcode: u8, slot: &[u8]) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 2);
+    hf.update(h1);
+    hf.update(&[opcode]);
+    hf.update(slot);
+    hf.finalize()
+}
+
+pub fn auth2store(psk: [u8; 32], h1: &[u8], opcode: u8, slot: &[u8], ts: &[u8]) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 2);
+    hf.update(h1);
+    hf.update(&[opcode]);
+    hf.update(slot);
+    hf.update(ts);
+    hf.finalize()
+}
+
+// version 1 clients authenticate the signature up front instead of the slot.
+pub fn auth2store_v1(
     psk: [u8; 32],
     h1: &[u8],
     opcode: u8,
@@ -46,6 +68,15 @@ pub fn auth2store(
     hf.finalize()
 }
 
+pub fn auth2watch(psk: [u8; 32], h1: &[u8], opcode: u8, slot: &[u8], seen: &[u8]) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 2);
+    hf.update(h1);
+    hf.update(&[opcode]);
+    hf.update(slot);
+    hf.update(seen);
+    hf.finalize()
+}
+
 pub fn auth3get(psk: [u8; 32], h2: &[u8], ts: &[u8], signature: &[u8]) -> blake2b::Hash {
     let mut hf = new_blake2b(psk, 3);
     hf.update(h2);
@@ -54,8 +85,36 @@ pub fn auth3get(psk: [u8; 32], h2: &[u8], ts: &[u8], signature: &[u8]) -> blake2
     hf.finalize()
 }
 
-pub fn auth3store(psk: [u8; 32], h2: &[u8]) -> blake2b::Hash {
+pub fn auth3history(psk: [u8; 32], h2: &[u8], entries: &[u8]) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 3);
+    hf.update(h2);
+    hf.update(entries);
+    hf.finalize()
+}
+
+pub fn auth3stat(psk: [u8; 32], h2: &[u8], stat: &[u8]) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 3);
+    hf.update(h2);
+    hf.update(stat);
+    hf.finalize()
+}
+
+pub fn auth3store(psk: [u8; 32], h2: &[u8], signature: &[u8]) -> blake2b::Hash {
     let mut hf = new_blake2b(psk, 3);
     hf.update(h2);
+    hf.update(signature);
+    hf.finalize()
+}
+
+pub fn auth4move(psk: [u8; 32], h3: &[u8]) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 4);
+    hf.update(h3);
+    hf.finalize()
+}
+
+pub fn auth5move(psk: [u8; 32], h4: &[u8], removed: u8) -> blake2b::Hash {
+    let mut hf = new_blake2b(psk, 5);
+    hf.update(h4);
+    hf.update(&[removed]);
     hf.finalize()
 }
@@ -0,0 +1,322 @@
+use crate::log;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+
+// failures are counted against the address they come from, as well as against
+// its subnet, which catches attackers that have a whole range to spread them
+// over. a subnet gets more leeway, since it may well have honest clients in it.
+const IP_THRESHOLD: u32 = 5;
+const SUBNET_THRESHOLD: u32 = 20;
+const IPV4_PREFIX: u8 = 24;
+const IPV6_PREFIX: u8 = 64;
+// failures further apart than this don't add up.
+const FAILURE_WINDOW: Duration = Duration::from_secs(600);
+// every ban lasts twice as long as the one before, up to a day. offenders are
+// forgotten a day after their last failure or the end of their last ban,
+// whichever is later, and start over from the minimum.
+const MIN_BAN: Duration = Duration::from_secs(60);
+const MAX_BAN: Duration = Duration::from_hours(24);
+const FORGET_AFTER: Duration = Duration::from_hours(24);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Offender {
+    Ip(IpAddr),
+    // the first address in the subnet, and the length of its prefix.
+    Subnet(IpAddr, u8),
+}
+
+impl Offender {
+    fn subnet(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX << (32 - IPV4_PREFIX);
+                let net = Ipv4Addr::from(u32::from(ip) & mask);
+                Self::Subnet(IpAddr::V4(net), IPV4_PREFIX)
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX << (128 - IPV6_PREFIX);
+                let net = Ipv6Addr::from(u128::from(ip) & mask);
+                Self::Subnet(IpAddr::V6(net), IPV6_PREFIX)
+            }
+        }
+    }
+
+    const fn kind(self) -> &'static str {
+        match self {
+            Self::Ip(_) => "ip",
+            Self::Subnet(..) => "subnet",
+        }
+    }
+}
+
+impl fmt::Display for Offender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Subnet(net, prefix) => write!(f, "{net}/{prefix}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Record {
+    failures: u32,
+    last_failure: Instant,
+    bans: u32,
+    banned_until: Option<Instant>,
+    forget_at: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Bans {
+    records: Mutex<HashMap<Offender, Record>>,
+}
+
+impl Bans {
+    // clients that authenticated successfully before aren't held responsible
+    // for what the rest of their subnet does.
+    pub fn is_banned(&self, ip: IpAddr, trusted: bool) -> bool {
+        self.is_banned_at(ip, trusted, Instant::now())
+    }
+
+    pub fn failure(&self, ip: IpAddr) {
+        self.failure_at(ip, Instant::now());
+    }
+
+    // whatever the address did before is forgiven once it gets in. its subnet
+    // is left alone.
+    pub fn success(&self, ip: IpAddr) {
+        self.records.lock().remove(&Offender::Ip(ip.to_canonical()));
+    }
+
+    // lifts the bans that ran out, and forgets offenders that have been quiet
+    // for long enough.
+    pub fn expire(&self) {
+        self.expire_at(Instant::now());
+    }
+
+    // the clock is passed in so that tests don't have to wait for it.
+    fn is_banned_at(&self, ip: IpAddr, trusted: bool, now: Instant) -> bool {
+        // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6
+        // addresses, which would otherwise get a subnet of their own.
+        let ip = ip.to_canonical();
+        let records = self.records.lock();
+        let banned = |offender| {
+            records
+                .get(&offender)
+                .and_then(|record| record.banned_until)
+                .is_some_and(|until| until > now)
+        };
+        banned(Offender::Ip(ip)) || (!trusted && banned(Offender::subnet(ip)))
+    }
+
+    fn failure_at(&self, ip: IpAddr, now: Instant) {
+        let ip = ip.to_canonical();
+        let mut banned = Vec::new();
+        let mut records = self.records.lock();
+        for (offender, threshold) in [
+            (Offender::Ip(ip), IP_THRESHOLD),
+            (Offender::subnet(ip), SUBNET_THRESHOLD),
+        ] {
+            let record = records.entry(offender).or_insert(Record {
+                failures: 0,
+                last_failure: now,
+                bans: 0,
+                banned_until: None,
+                forget_at: now,
+            });
+            if now.duration_since(record.last_failure) > FAILURE_WINDOW {
+                record.failures = 0;
+            }
+            record.failures += 1;
+            record.last_failure = now;
+            record.forget_at = record.forget_at.max(now + FORGET_AFTER);
+            if record.failures < threshold {
+                continue;
+            }
+            let duration = MIN_BAN
+                .saturating_mul(1 << record.bans.min(16))
+                .min(MAX_BAN);
+            record.failures = 0;
+            record.bans += 1;
+            record.banned_until = Some(now + duration);
+            record.forget_at = now + duration + FORGET_AFTER;
+            banned.push((offender, duration, record.bans));
+        }
+        drop(records);
+        for (offender, duration, bans) in banned {
+            log::warn(
+                "banned",
+                &[
+                    (offender.kind(), offender.to_string().as_str().into()),
+                    ("seconds", duration.as_secs().into()),
+                    ("bans", u64::from(bans).into()),
+                ],
+            );
+        }
+    }
+
+    fn expire_at(&self, now: Instant) {
+        self.records.lock().retain(|offender, record| {
+            if record.banned_until.is_some_and(|until| until <= now) {
+                record.banned_until = None;
+                log::info(
+                    "unbanned",
+                    &[(offender.kind(), offender.to_string().as_str().into())],
+                );
+            }
+            record.banned_until.is_some() || now < record.forget_at
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().expect("the address is valid")
+    }
+
+    fn banned_for(bans: &Bans, offender: Offender, now: Instant) -> Option<Duration> {
+        bans.records
+            .lock()
+            .get(&offender)
+            .and_then(|record| record.banned_until)
+            .map(|until| until - now)
+    }
+
+    #[test]
+    fn test_threshold() {
+        let bans = Bans::default();
+        let now = Instant::now();
+        let client = ip("192.0.2.1");
+        for _ in 1..IP_THRESHOLD {
+            bans.failure_at(client, now);
+            assert!(!bans.is_banned_at(client, false, now));
+        }
+        bans.failure_at(client, now);
+        assert!(bans.is_banned_at(client, false, now));
+        assert!(bans.is_banned_at(client, true, now));
+        assert!(!bans.is_banned_at(ip("192.0.2.2"), false, now));
+        assert!(bans.is_banned_at(
+            client,
+            false,
+            now + MIN_BAN.saturating_sub(Duration::from_secs(1))
+        ));
+        assert!(!bans.is_banned_at(client, false, now + MIN_BAN));
+    }
+
+    #[test]
+    fn test_window() {
+        let bans = Bans::default();
+        let mut now = Instant::now();
+        let client = ip("2001:db8::1");
+        for _ in 1..IP_THRESHOLD {
+            bans.failure_at(client, now);
+        }
+        // the failures before are too old to count.
+        now += FAILURE_WINDOW + Duration::from_secs(1);
+        for _ in 1..IP_THRESHOLD {
+            bans.failure_at(client, now);
+            assert!(!bans.is_banned_at(client, false, now));
+        }
+        // but these are close enough together.
+        now += FAILURE_WINDOW;
+        bans.failure_at(client, now);
+        assert!(bans.is_banned_at(client, false, now));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let bans = Bans::default();
+        let mut now = Instant::now();
+        let client = ip("198.51.100.7");
+        let mut expected = MIN_BAN;
+        for _ in 0..16 {
+            for _ in 0..IP_THRESHOLD {
+                bans.failure_at(client, now);
+            }
+            assert_eq!(banned_for(&bans, Offender::Ip(client), now), Some(expected));
+            now += expected;
+            bans.expire_at(now);
+            assert!(!bans.is_banned_at(client, true, now));
+            expected = (expected * 2).min(MAX_BAN);
+        }
+        assert_eq!(expected, MAX_BAN);
+        // offenders that stay away long enough start over.
+        now += FORGET_AFTER;
+        bans.expire_at(now);
+        for _ in 0..IP_THRESHOLD {
+            bans.failure_at(client, now);
+        }
+        assert_eq!(banned_for(&bans, Offender::Ip(client), now), Some(MIN_BAN));
+    }
+
+    #[test]
+    fn test_subnet() {
+        let bans = Bans::default();
+        let now = Instant::now();
+        // spread thin enough that no single address is banned.
+        let per_ip = IP_THRESHOLD - 1;
+        let clients = SUBNET_THRESHOLD.div_ceil(per_ip);
+        for i in 0..clients {
+            let client = ip(&format!("203.0.113.{i}"));
+            for _ in 0..per_ip {
+                bans.failure_at(client, now);
+            }
+            assert!(!bans.is_banned_at(client, true, now));
+        }
+        let neighbour = ip("203.0.113.200");
+        assert!(bans.is_banned_at(neighbour, false, now));
+        assert!(bans.is_banned_at(ip("::ffff:203.0.113.200"), false, now));
+        assert!(!bans.is_banned_at(neighbour, true, now));
+        assert!(!bans.is_banned_at(ip("203.0.114.1"), false, now));
+        assert_eq!(
+            banned_for(&bans, Offender::subnet(neighbour), now),
+            Some(MIN_BAN)
+        );
+
+        let v6 = |i: u32| ip(&format!("2001:db8:0:1:{i:x}::1"));
+        for i in 0..clients {
+            for _ in 0..per_ip {
+                bans.failure_at(v6(i), now);
+            }
+        }
+        assert!(bans.is_banned_at(ip("2001:db8:0:1:ffff::"), false, now));
+        assert!(!bans.is_banned_at(ip("2001:db8:0:2::1"), false, now));
+    }
+
+    #[test]
+    fn test_expire() {
+        let bans = Bans::default();
+        let now = Instant::now();
+        let client = ip("192.0.2.1");
+        for _ in 0..IP_THRESHOLD {
+            bans.failure_at(client, now);
+        }
+        bans.expire_at(now + MIN_BAN.saturating_sub(Duration::from_secs(1)));
+        assert!(banned_for(&bans, Offender::Ip(client), now).is_some());
+        bans.expire_at(now + MIN_BAN);
+        assert!(banned_for(&bans, Offender::Ip(client), now).is_none());
+        assert!(!bans.is_banned_at(client, false, now));
+        // the record is kept, so the next ban lasts longer.
+        assert!(bans.records.lock().contains_key(&Offender::Ip(client)));
+        bans.expire_at(now + MIN_BAN + FORGET_AFTER.saturating_sub(Duration::from_secs(1)));
+        assert!(bans.records.lock().contains_key(&Offender::Ip(client)));
+        bans.expire_at(now + MIN_BAN + FORGET_AFTER);
+        assert!(bans.records.lock().is_empty());
+        // and success forgives the address, but not its subnet.
+        for _ in 0..IP_THRESHOLD {
+            bans.failure_at(client, now);
+        }
+        bans.success(client);
+        assert!(!bans.is_banned_at(client, false, now));
+        assert!(bans.records.lock().contains_key(&Offender::subnet(client)));
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+// a range of addresses, written as `address/prefix`. a bare address stands for
+// itself alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+fn mask(addr: IpAddr, prefix: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(addr) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask))
+        }
+        IpAddr::V6(addr) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask))
+        }
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6
+        // addresses.
+        let ip = ip.to_canonical();
+        ip.is_ipv4() == self.addr.is_ipv4() && mask(ip, self.prefix) == self.addr
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = ();
+
+    // host bits are rejected rather than ignored, since they are more likely
+    // to be a typo than not.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(a, p)| (a, Some(p)));
+        let addr = addr.parse::<IpAddr>().map_err(drop)?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) if prefix.bytes().all(|b| b.is_ascii_digit()) => {
+                prefix.parse::<u8>().map_err(drop)?
+            }
+            Some(_) => return Err(()),
+            None => max,
+        };
+        if prefix > max || mask(addr, prefix) != addr {
+            return Err(());
+        }
+        // clients are compared in their canonical form, so IPv4-mapped ranges
+        // have to be in it too to ever match.
+        let (addr, prefix) = match addr {
+            IpAddr::V6(v6) if prefix >= 96 => v6
+                .to_ipv4_mapped()
+                .map_or((addr, prefix), |v4| (IpAddr::V4(v4), prefix - 96)),
+            _ => (addr, prefix),
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(s: &str) -> Cidr {
+        s.parse()
+            .unwrap_or_else(|()| panic!("{s} is a valid range"))
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().expect("the address is valid")
+    }
+
+    #[test]
+    fn test_v4() {
+        let range = cidr("10.8.0.0/16");
+        assert!(range.contains(ip("10.8.0.0")));
+        assert!(range.contains(ip("10.8.255.255")));
+        assert!(!range.contains(ip("10.9.0.0")));
+        assert!(!range.contains(ip("::a08:1")));
+        // IPv4 clients of a dual-stack listener.
+        assert!(range.contains(ip("::ffff:10.8.1.1")));
+        assert!(cidr("192.0.2.7").contains(ip("192.0.2.7")));
+        assert!(!cidr("192.0.2.7").contains(ip("192.0.2.6")));
+    }
+
+    #[test]
+    fn test_v6() {
+        let range = cidr("2001:db8::/32");
+        assert!(range.contains(ip("2001:db8::1")));
+        assert!(range.contains(ip("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert!(!range.contains(ip("2001:db9::")));
+        assert!(!range.contains(ip("32.1.13.184")));
+        assert!(cidr("::1").contains(ip("::1")));
+        assert!(!cidr("::1").contains(ip("127.0.0.1")));
+    }
+
+    #[test]
+    fn test_mapped() {
+        assert_eq!(cidr("::ffff:10.8.0.0/112"), cidr("10.8.0.0/16"));
+        assert_eq!(cidr("::ffff:192.0.2.7"), cidr("192.0.2.7"));
+        assert!(cidr("::ffff:10.8.0.0/112").contains(ip("10.8.1.1")));
+        assert!(cidr("::ffff:10.8.0.0/112").contains(ip("::ffff:10.8.1.1")));
+    }
+
+    #[test]
+    fn test_prefix_edges() {
+        assert!(cidr("0.0.0.0/0").contains(ip("255.255.255.255")));
+        assert!(cidr("0.0.0.0/0").contains(ip("::ffff:1.2.3.4")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("::1")));
+        assert!(cidr("::/0").contains(ip("ffff::")));
+        assert!(!cidr("::/0").contains(ip("1.2.3.4")));
+        assert_eq!(cidr("1.2.3.4/32"), cidr("1.2.3.4"));
+        assert!(!cidr("1.2.3.4/32").contains(ip("1.2.3.5")));
+        assert_eq!(cidr("2001:db8::1/128"), cidr("2001:db8::1"));
+        assert!(!cidr("2001:db8::1/128").contains(ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn test_malformed() {
+        for s in [
+            "",
+            "/",
+            "10.0.0.0/",
+            "10.0.0.0/33",
+            "10.0.0.0/+8",
+            "10.0.0.0/ 8",
+            "10.0.0.0/8/8",
+            "10.0.0.1/8",
+            "10.0.0/8",
+            "::/129",
+            "::1/64",
+            "2001:db8::/256",
+            "example.com",
+        ] {
+            assert!(s.parse::<Cidr>().is_err(), "{s}");
+        }
+    }
+}
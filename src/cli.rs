@@ -1,11 +1,16 @@
 use crate::{
+    client::{CopyOptions, HistoryOptions, Operation, PasteOptions, StatusOptions},
     config::{Config, TomlConfig},
     error::{Context, Error, ResultExt},
+    log::{Format, Level},
     state::State,
 };
-use clap::{Parser, Subcommand};
+use clap::{error::ErrorKind, ArgGroup, CommandFactory, Parser, Subcommand};
 use platform::env::home_dir;
-use std::{num::NonZeroUsize, path::PathBuf};
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Parser)]
 #[clap(about, author, version = crate::EXPANDED_VERSION)]
@@ -23,17 +28,21 @@ pub struct Cli {
     pub config: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone, Copy, Subcommand)]
+#[derive(Debug, Clone, Subcommand)]
 pub enum Command {
     /// store content
     #[clap(alias = "c")]
-    Copy,
+    Copy(CopyArgs),
     /// retrieve content
     #[clap(alias = "p")]
-    Paste,
+    Paste(PasteArgs),
     /// retrieve and delete content
     #[clap(alias = "m")]
-    Move,
+    Move(RetrieveArgs),
+    /// list the stored content history
+    History(HistoryArgs),
+    /// describe content without retrieving it
+    Status(StatusArgs),
     /// start a server
     Serve(ServerArgs),
     /// generate keys
@@ -43,6 +52,173 @@ pub enum Command {
     Version,
 }
 
+#[derive(Debug, Clone, Parser)]
+#[clap(about, author, version = crate::EXPANDED_VERSION)]
+#[clap(help_template = r"{name} {version}
+{author-with-newline}{about-with-newline}
+{usage-heading} {usage}
+
+{all-args}")]
+pub struct ClientArgs {
+    /// the clipboard slot to operate on (default=the unnamed slot)
+    #[clap(short, long, default_value = "")]
+    pub slot: String,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[clap(about, author, version = crate::EXPANDED_VERSION)]
+#[clap(help_template = r"{name} {version}
+{author-with-newline}{about-with-newline}
+{usage-heading} {usage}
+
+{all-args}")]
+pub struct CopyArgs {
+    #[clap(flatten)]
+    pub client: ClientArgs,
+    /// the file to copy (default=standard input)
+    pub file: Option<PathBuf>,
+    /// copy FILE as a directory, with everything in it
+    #[clap(short, long, requires = "file")]
+    pub recursive: bool,
+    /// encrypt for these recipient public keys instead of the shared key
+    #[clap(long, num_args = 1.., value_name = "RECIPIENT_PK", value_parser = parse_recipient_pk)]
+    pub to: Vec<[u8; 32]>,
+    /// send the content as is, without trying to compress it
+    #[clap(long)]
+    pub no_compress: bool,
+    /// the file name to record along with the content
+    #[clap(long, value_parser = parse_field)]
+    pub name: Option<String>,
+    /// the content type to record along with the content (default=guessed)
+    #[clap(long = "type", value_name = "TYPE", value_parser = parse_field, conflicts_with = "recursive")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[clap(about, author, version = crate::EXPANDED_VERSION)]
+#[clap(help_template = r"{name} {version}
+{author-with-newline}{about-with-newline}
+{usage-heading} {usage}
+
+{all-args}")]
+#[clap(group(ArgGroup::new("dest").args(["output", "recursive"]).multiple(true)))]
+pub struct RetrieveArgs {
+    #[clap(flatten)]
+    pub client: ClientArgs,
+    /// the history entry to retrieve, counting back from the newest (0)
+    #[clap(short, long, default_value = "0")]
+    pub index: u64,
+    /// retrieve content that was sent to your recipient key
+    #[clap(long)]
+    pub received: bool,
+    /// save the content to a file, or to a directory under the name it was sent with
+    #[clap(short, long, value_name = "PATH")]
+    pub output: Option<PathBuf>,
+    /// extract a directory sent with copy -r into --output (default=the current directory)
+    #[clap(short, long)]
+    pub recursive: bool,
+    /// overwrite the file or directory being saved if it already exists
+    #[clap(long, requires = "dest")]
+    pub force: bool,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[clap(about, author, version = crate::EXPANDED_VERSION)]
+#[clap(help_template = r"{name} {version}
+{author-with-newline}{about-with-newline}
+{usage-heading} {usage}
+
+{all-args}")]
+pub struct PasteArgs {
+    #[clap(flatten)]
+    pub retrieve: RetrieveArgs,
+    /// print the file name, type, size and mode instead of the content
+    #[clap(long, conflicts_with = "recursive")]
+    pub info: bool,
+    /// keep running, and paste every new item as soon as it is copied
+    #[clap(long, conflicts_with_all = ["index", "info"])]
+    pub watch: bool,
+    /// run COMMAND for every new item, with the content on its standard input
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        requires = "watch",
+        conflicts_with = "dest"
+    )]
+    pub exec: Option<String>,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[clap(about, author, version = crate::EXPANDED_VERSION)]
+#[clap(help_template = r"{name} {version}
+{author-with-newline}{about-with-newline}
+{usage-heading} {usage}
+
+{all-args}")]
+pub struct HistoryArgs {
+    #[clap(flatten)]
+    pub client: ClientArgs,
+    /// list content that was sent to your recipient key
+    #[clap(long)]
+    pub received: bool,
+}
+
+#[derive(Debug, Clone, Parser)]
+#[clap(about, author, version = crate::EXPANDED_VERSION)]
+#[clap(help_template = r"{name} {version}
+{author-with-newline}{about-with-newline}
+{usage-heading} {usage}
+
+{all-args}")]
+pub struct StatusArgs {
+    #[clap(flatten)]
+    pub client: ClientArgs,
+    /// the history entry to describe, counting back from the newest (0)
+    #[clap(short, long, default_value = "0")]
+    pub index: u64,
+    /// describe content that was sent to your recipient key
+    #[clap(long)]
+    pub received: bool,
+    /// print a JSON object instead of text
+    #[clap(long)]
+    pub json: bool,
+}
+
+fn parse_field(s: &str) -> Result<String, String> {
+    if s.len() > crate::envelope::MAX_FIELD_LEN {
+        return Err(format!(
+            "expected at most {} bytes",
+            crate::envelope::MAX_FIELD_LEN
+        ));
+    }
+    Ok(s.to_owned())
+}
+
+// every new item would be saved to the same place, which only the first one
+// is allowed to take without --force.
+fn check_watch(args: &PasteArgs) {
+    let retrieve = &args.retrieve;
+    if args.watch && (retrieve.output.is_some() || retrieve.recursive) && !retrieve.force {
+        let mut command = Cli::command();
+        command.build();
+        command
+            .find_subcommand_mut("paste")
+            .expect("paste is a subcommand")
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--watch needs --force to save to --output or extract with --recursive",
+            )
+            .exit();
+    }
+}
+
+fn parse_recipient_pk(s: &str) -> Result<[u8; 32], String> {
+    let mut buf = [0; 32];
+    crate::util::from_hex(s, &mut buf)
+        .map_err(|()| "expected 64 hexadecimal characters".to_owned())?;
+    Ok(buf)
+}
+
 #[derive(Debug, Clone, Copy, Parser)]
 #[clap(about, author, version = crate::EXPANDED_VERSION)]
 #[clap(help_template = r"{name} {version}
@@ -63,6 +239,15 @@ pub struct ServerArgs {
     /// data transmission timeout (in seconds)
     #[clap(short, long, default_value = "3600")]
     pub data_timeout: u64,
+    /// how long open connections get to finish when shutting down (in seconds)
+    #[clap(long, default_value = "30")]
+    pub grace_period: u64,
+    /// the least severe messages to log (default=info)
+    #[clap(long, value_enum)]
+    pub log_level: Option<Level>,
+    /// the format to log messages in (default=text)
+    #[clap(long, value_enum)]
+    pub log_format: Option<Format>,
 }
 
 #[derive(Debug, Parser, Clone, Copy)]
@@ -79,11 +264,9 @@ pub struct KeygenArgs {
 }
 
 impl Cli {
-    pub async fn run() -> Result<(), Context> {
-        let cli = Self::parse();
-
-        if let Command::Keygen(KeygenArgs { password }) = cli.subcommand {
-            let config_file = match cli.config {
+    pub async fn run(self) -> Result<(), Context> {
+        if let Command::Keygen(KeygenArgs { password }) = self.subcommand {
+            let config_file = match self.config {
                 Some(config_file) => config_file,
                 None => Self::default_config_file()?,
             };
@@ -95,10 +278,84 @@ impl Cli {
             crate::keygen::generate_keys(config_file.display(), key.as_bytes());
             return Ok(());
         }
-        let config_file = match &cli.config {
+        let config_file = match &self.config {
             Some(config_file) => config_file.clone(),
             None => Self::default_config_file()?,
         };
+        let config = self.load_config(&config_file)?;
+        let ret = match self.subcommand {
+            Command::Version => {
+                println!("{}", crate::EXPANDED_VERSION);
+                Ok(())
+            }
+            Command::Copy(args) => {
+                let op = Operation::Copy(CopyOptions {
+                    file: args.file,
+                    to: args.to,
+                    compress: !args.no_compress,
+                    name: args.name,
+                    content_type: args.content_type,
+                    recursive: args.recursive,
+                });
+                crate::client::run(config, &args.client.slot, op).await
+            }
+            Command::Move(args) => {
+                let op = Operation::Move(PasteOptions {
+                    index: args.index,
+                    received: args.received,
+                    info: false,
+                    output: args.output,
+                    recursive: args.recursive,
+                    force: args.force,
+                    exec: None,
+                });
+                crate::client::run(config, &args.client.slot, op).await
+            }
+            Command::Paste(args) => {
+                check_watch(&args);
+                let options = PasteOptions {
+                    index: args.retrieve.index,
+                    received: args.retrieve.received,
+                    info: args.info,
+                    output: args.retrieve.output,
+                    recursive: args.retrieve.recursive,
+                    force: args.retrieve.force,
+                    exec: args.exec,
+                };
+                let op = if args.watch {
+                    Operation::Watch(options)
+                } else {
+                    Operation::Paste(options)
+                };
+                crate::client::run(config, &args.retrieve.client.slot, op).await
+            }
+            Command::History(args) => {
+                let op = Operation::History(HistoryOptions {
+                    received: args.received,
+                });
+                crate::client::run(config, &args.client.slot, op).await
+            }
+            Command::Status(args) => {
+                let op = Operation::Status(StatusOptions {
+                    index: args.index,
+                    received: args.received,
+                    json: args.json,
+                });
+                crate::client::run(config, &args.client.slot, op).await
+            }
+            Command::Serve(_) => {
+                // loading the stored content may already have something to say.
+                crate::log::init(config.log_level(), config.log_format());
+                crate::server::serve(State::new(config)?, self, config_file).await
+            }
+            Command::Keygen(_) => unreachable!(),
+        };
+        Ok(ret?)
+    }
+
+    // the server calls this again to reload its configuration, with the same
+    // command line arguments.
+    pub fn load_config(&self, config_file: &Path) -> Result<Config, Context> {
         let config = toml::from_str::<toml::value::Table>(
             &std::fs::read_to_string(config_file.canonicalize().context(format!(
                 "failed to canonicalize config file path '{}'",
@@ -111,19 +368,7 @@ impl Cli {
         )
         .context("while parsing config file")?;
         let toml_config = TomlConfig::new(config);
-        let config = Config::new(&toml_config, &cli)?;
-        let ret = match cli.subcommand {
-            Command::Version => {
-                println!("{}", crate::EXPANDED_VERSION);
-                Ok(())
-            }
-            Command::Copy => crate::client::run(config, true, false).await,
-            Command::Move => crate::client::run(config, false, true).await,
-            Command::Paste => crate::client::run(config, false, false).await,
-            Command::Serve(_) => crate::server::serve(State::new(config)).await,
-            Command::Keygen(_) => unreachable!(),
-        };
-        Ok(ret?)
+        Ok(Config::new(&toml_config, self)?)
     }
 
     fn default_config_file() -> Result<PathBuf, Error> {
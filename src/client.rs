@@ -1,79 +1,274 @@
 use crate::{
-    authentication::{auth0, auth1, auth2get, auth2store, auth3get, auth3store},
+    archive::{self, Packer, Unpacker},
+    authentication::{
+        auth0, auth1, auth2get, auth2history, auth2store, auth2watch, auth3get, auth3history,
+        auth3stat, auth3store, auth4move, auth5move,
+    },
+    compression::{Compression, BLOCK_SIZE},
     config::Config,
+    envelope::{
+        self, Header, Opener, Sealer, CHUNK_SIZE, HEADER_LEN, MAX_FIELD_LEN, SIGNATURE_LEN, TAG_LEN,
+    },
     error::Error,
-    util::Stream,
+    mime,
+    output::Output,
+    session::Ephemeral,
+    util::{hex, human_age, human_size, json_string, Stream},
 };
 use crypto_common::constant_time::ConstantTimeEq;
 use platform::tty::isatty;
 use rand_core::RngCore;
 use std::{
-    io::{self, Read, Write},
+    collections::VecDeque,
+    env,
+    fs::{self, File, Metadata},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
     net::TcpStream,
+    path::{Path, PathBuf},
+    process::Command,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[macro_export]
 macro_rules! default_client_version {
     () => {
-        1
+        8
     };
 }
 
 const DEFAULT_CLIENT_VERSION: u8 = crate::default_client_version!();
+// the first version with the session layer.
+const SESSION_VERSION: u8 = 3;
+// the first version with the watch opcode.
+const WATCH_VERSION: u8 = 5;
+// the first version with the stat opcode.
+const STAT_VERSION: u8 = 6;
+// the first version where moved content is only removed once we say it was
+// saved.
+const MOVE_VERSION: u8 = 8;
+// how long to wait before watching again after a failure, doubling each time.
+const WATCH_RETRY_MIN: Duration = Duration::from_secs(1);
+const WATCH_RETRY_MAX: Duration = Duration::from_secs(60);
+// how many of the entries pasted last a watch remembers, so that it doesn't
+// paste them again when they come back as the newest entry.
+const WATCH_REMEMBER: usize = 1024;
 
-async fn copy_operation(config: &Config, s: &mut Stream, h1: &[u8]) -> Result<(), Error> {
+#[derive(Debug, Clone)]
+pub enum Operation {
+    Copy(CopyOptions),
+    Paste(PasteOptions),
+    Move(PasteOptions),
+    History(HistoryOptions),
+    // paste every new item as it comes in.
+    Watch(PasteOptions),
+    Status(StatusOptions),
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    // standard input is read if there is no file.
+    pub file: Option<PathBuf>,
+    // recipient public keys, if the content isn't meant for everyone.
+    pub to: Vec<[u8; 32]>,
+    pub compress: bool,
+    pub name: Option<String>,
+    pub content_type: Option<String>,
+    // send `file` as a directory.
+    pub recursive: bool,
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct PasteOptions {
+    pub index: u64,
+    pub received: bool,
+    // print the header instead of the content.
+    pub info: bool,
+    // standard output is written to if there is no path.
+    pub output: Option<PathBuf>,
+    // extract a directory into `output`, or the current directory.
+    pub recursive: bool,
+    pub force: bool,
+    // hand the content to this command instead of writing it out.
+    pub exec: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryOptions {
+    pub received: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusOptions {
+    pub index: u64,
+    pub received: bool,
+    pub json: bool,
+}
+
+struct Input {
+    reader: Box<dyn Read + Send>,
+    // the size and mode are only known if the input is a regular file.
+    metadata: Option<Metadata>,
+    name: Option<String>,
+    content_type: Option<&'static str>,
+}
+
+impl Input {
+    fn open(options: &CopyOptions) -> Result<Self, Error> {
+        let mut input = match &options.file {
+            Some(path) if options.recursive => {
+                let (packer, name) =
+                    Packer::new(path, options.name.as_deref()).map_err(|e| match e {
+                        Error::Io(e) => {
+                            io::Error::new(e.kind(), format!("{}: {e}", path.display())).into()
+                        }
+                        e => e,
+                    })?;
+                Self {
+                    reader: Box::new(packer),
+                    metadata: None,
+                    name: Some(name),
+                    content_type: Some(archive::CONTENT_TYPE),
+                }
+            }
+            Some(path) => {
+                let with_path =
+                    |e: io::Error| io::Error::new(e.kind(), format!("{}: {e}", path.display()));
+                let file = File::open(path).map_err(with_path)?;
+                let metadata = file.metadata().map_err(with_path)?;
+                if metadata.is_dir() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::IsADirectory,
+                        format!("{}: is a directory (use -r to copy it)", path.display()),
+                    )
+                    .into());
+                }
+                Self {
+                    reader: Box::new(file),
+                    metadata: Some(metadata),
+                    name: path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned()),
+                    content_type: None,
+                }
+            }
+            None => Self {
+                reader: Box::new(io::stdin()),
+                metadata: platform::fs::stdin_metadata().filter(Metadata::is_file),
+                name: None,
+                content_type: None,
+            },
+        };
+        if options.name.is_some() {
+            input.name.clone_from(&options.name);
+        }
+        if let Some(name) = input
+            .name
+            .as_ref()
+            .filter(|name| name.len() > MAX_FIELD_LEN)
+        {
+            return Err(Error::InvalidFileName(name.clone()));
+        }
+        Ok(input)
+    }
+}
+
+fn read_chunk(r: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+async fn write_frame(
+    s: &mut Stream,
+    sealer: &mut Sealer<'_>,
+    chunk: &mut [u8],
+    last: bool,
+) -> Result<(), Error> {
+    let (word, tag, chunk_signature) = sealer.seal(chunk, last);
+    s.write_all(&word).await?;
+    s.write_all(chunk).await?;
+    s.write_all(&tag).await?;
+    s.write_all(&chunk_signature).await?;
+    Ok(())
+}
+
+async fn copy_operation(
+    config: &Config,
+    s: &mut Stream,
+    h1: &[u8],
+    slot: &[u8; 32],
+    options: &CopyOptions,
+    mut input: Input,
+) -> Result<(), Error> {
     let ts = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("system clock is broken")
         .as_secs()
         .to_le_bytes();
-    let mut content_with_encrypt_sk_id_and_nonce = vec![0; 32];
-    content_with_encrypt_sk_id_and_nonce[..8]
-        .copy_from_slice(&config.encrypt_sk_id().to_le_bytes());
-    let mut rng = rand_core::OsRng;
-    rng.fill_bytes(&mut content_with_encrypt_sk_id_and_nonce[8..32]);
-    io::stdin()
-        .lock()
-        .read_to_end(&mut content_with_encrypt_sk_id_and_nonce)?;
     let opcode = b'S';
-    let mut cipher = xchacha20::XChaCha20::new(
-        &config.encrypt_sk(),
-        &content_with_encrypt_sk_id_and_nonce[8..32]
-            .try_into()
-            .expect("8..32 doesn't span 24 bytes. math has died."),
-    );
-    let ct = &mut content_with_encrypt_sk_id_and_nonce[32..];
-    cipher.apply_keystream(ct);
-    assert_eq!(
-        &content_with_encrypt_sk_id_and_nonce[0..8],
-        &config.encrypt_sk_id().to_le_bytes()
-    );
-    let signature = config
-        .sign_sk()
-        .sign(content_with_encrypt_sk_id_and_nonce.as_slice());
     s.set_timeout(config.data_timeout());
-    let h2 = auth2store(config.psk(), h1, opcode, &ts, &signature.to_bytes());
+    let h2 = auth2store(config.psk(), h1, opcode, slot, &ts);
     s.write_all(&[opcode]).await?;
+    s.write_all(slot).await?;
     s.write_all(h2.as_bytes()).await?;
-    let ciphertext_with_encrypt_sk_id_and_nonce_len =
-        content_with_encrypt_sk_id_and_nonce.len() as u64;
-    s.write_all(&ciphertext_with_encrypt_sk_id_and_nonce_len.to_le_bytes())
-        .await?;
     s.write_all(&ts).await?;
-    s.write_all(&signature.to_bytes()).await?;
-    s.write_all(&content_with_encrypt_sk_id_and_nonce).await?;
+    let (mut sealer, header) = Sealer::new(config, &options.to, config.sign_sk())?;
+    s.write_all(&header).await?;
+    let mut block = vec![0; BLOCK_SIZE];
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut len = read_chunk(&mut input.reader, &mut block)?;
+    let compression = if options.compress {
+        Compression::Lz4
+    } else {
+        Compression::None
+    };
+    let mut header = Header {
+        compression,
+        name: input.name,
+        content_type: Some(
+            options
+                .content_type
+                .clone()
+                .or_else(|| input.content_type.map(str::to_owned))
+                .unwrap_or_else(|| mime::sniff(&block[..len]).to_owned()),
+        ),
+        size: input.metadata.as_ref().map(Metadata::len),
+        mode: input.metadata.as_ref().and_then(platform::fs::mode),
+    }
+    .to_bytes();
+    write_frame(s, &mut sealer, &mut header, false).await?;
+    loop {
+        // a short read means we've hit EOF. if the input happens to end on a
+        // block boundary, the last block is simply empty.
+        let last = len < BLOCK_SIZE;
+        let compressed = compression.compress(&block[..len], &mut chunk);
+        write_frame(s, &mut sealer, &mut chunk[..compressed], last).await?;
+        if last {
+            break;
+        }
+        len = read_chunk(&mut input.reader, &mut block)?;
+    }
+    let signature = sealer.finish().to_bytes();
+    s.write_all(&signature).await?;
     s.flush().await?;
     let mut rbuf = [0; 32];
     s.read_exact(&mut rbuf).await.map_err(|e| {
         if e.kind() == io::ErrorKind::UnexpectedEof {
-            Error::MaybeIncompatibleVersion
+            Error::Rejected
         } else {
             e.into()
         }
     })?;
     let h3 = &rbuf[..32];
-    let wh3 = auth3store(config.psk(), h2.as_bytes());
+    let wh3 = auth3store(config.psk(), h2.as_bytes(), &signature);
     if wh3.as_bytes().ct_eq(h3).to_u8() != 1 {
         return Err(Error::Auth);
     }
@@ -83,38 +278,77 @@ async fn copy_operation(config: &Config, s: &mut Stream, h1: &[u8]) -> Result<()
     Ok(())
 }
 
-#[allow(clippy::cast_possible_truncation)]
+// reads everything up to the first frame, from the format to the signature of
+// the header.
+async fn read_envelope_header(stream: &mut Stream) -> Result<Vec<u8>, Error> {
+    let mut header = vec![0; 1 + HEADER_LEN];
+    stream.read_exact(&mut header).await?;
+    // nothing past the format means the same thing in other formats.
+    if header[0] != envelope::FORMAT {
+        return Err(Error::UnknownFormat(header[0]));
+    }
+    if envelope::has_recipients(&header[1..]) {
+        let mut count = [0; 4];
+        stream.read_exact(&mut count).await?;
+        let len = envelope::recipients_len(count)?;
+        header.extend_from_slice(&count);
+        header.resize(header.len() + len, 0);
+        stream
+            .read_exact(&mut header[1 + HEADER_LEN + count.len()..])
+            .await?;
+    }
+    let start = header.len();
+    header.resize(start + SIGNATURE_LEN, 0);
+    stream.read_exact(&mut header[start..]).await?;
+    Ok(header)
+}
+
+// returns the signature of what was pasted, which identifies it.
 async fn paste_operation(
     config: &Config,
     stream: &mut Stream,
     h1: &[u8],
+    slot: &[u8; 32],
     is_move: bool,
-) -> Result<(), Error> {
+    options: &PasteOptions,
+) -> Result<[u8; 64], Error> {
     let opcode = if is_move { b'M' } else { b'G' };
-    let h2 = auth2get(config.psk(), h1, opcode);
+    let index = options.index.to_le_bytes();
+    let h2 = auth2get(config.psk(), h1, opcode, slot, &index);
     stream.write_all(&[opcode]).await?;
+    stream.write_all(slot).await?;
+    stream.write_all(&index).await?;
     stream.write_all(h2.as_bytes()).await?;
     stream.flush().await?;
-    let mut rbuf = [0; 112];
+    let mut rbuf = [0; 40];
     stream.read_exact(&mut rbuf).await.map_err(|e| {
         if e.kind() == io::ErrorKind::UnexpectedEof {
-            if rbuf.iter().position(|&b| b == 0).is_some_and(|p| p > 80) {
-                Error::MaybeIncompatibleVersion
-            } else {
-                Error::Empty
-            }
+            Error::Rejected
         } else {
             e.into()
         }
     })?;
-    let mut len_buf = [0; 8];
-    len_buf.copy_from_slice(&rbuf[32..40]);
     let h3 = &rbuf[..32];
-    let ciphertext_with_encrypt_sk_id_and_nonce_len = u64::from_le_bytes(len_buf);
+    let ciphertext_with_encrypt_sk_id_and_nonce_len = u64::from_le_bytes(
+        rbuf[32..40]
+            .try_into()
+            .expect("32..40 doesn't span 8 bytes"),
+    );
+    // an empty slot is answered with just a zero length, authenticated like
+    // everything else.
+    if ciphertext_with_encrypt_sk_id_and_nonce_len == 0 {
+        let wh3 = auth3get(config.psk(), h2.as_bytes(), &0u64.to_le_bytes(), &[]);
+        if wh3.as_bytes().ct_eq(h3).to_u8() != 1 {
+            return Err(Error::Auth);
+        }
+        return Err(Error::Empty);
+    }
+    let mut rbuf = [0; 72];
+    stream.read_exact(&mut rbuf).await?;
     let mut ts = [0; 8];
-    ts.copy_from_slice(&rbuf[40..48]);
+    ts.copy_from_slice(&rbuf[..8]);
     let mut signature = [0; 64];
-    signature.copy_from_slice(&rbuf[48..112]);
+    signature.copy_from_slice(&rbuf[8..72]);
     let wh3 = auth3get(config.psk(), h2.as_bytes(), &ts, &signature);
     if wh3.as_bytes().ct_eq(h3).to_u8() != 1 {
         return Err(Error::Auth);
@@ -125,66 +359,422 @@ async fn paste_operation(
     if elapsed >= config.ttl() {
         return Err(Error::Old);
     }
-    if ciphertext_with_encrypt_sk_id_and_nonce_len < 32 {
+    if ciphertext_with_encrypt_sk_id_and_nonce_len < 1 + HEADER_LEN as u64 {
         return Err(Error::Short);
     }
-    let mut ciphertext_with_encrypt_sk_id_and_nonce =
-        Vec::with_capacity(ciphertext_with_encrypt_sk_id_and_nonce_len as usize);
     stream.set_timeout(config.data_timeout());
-    stream
-        .read_to_end(&mut ciphertext_with_encrypt_sk_id_and_nonce)
-        .await
-        .map_err(|e| {
-            if e.kind() == io::ErrorKind::UnexpectedEof {
-                Error::MaybeIncompatibleVersion
-            } else {
-                e.into()
+    read_content(
+        config,
+        stream,
+        ciphertext_with_encrypt_sk_id_and_nonce_len,
+        &signature,
+        options,
+    )
+    .await?;
+    if is_move {
+        remove_operation(config, stream, h3).await?;
+    }
+    Ok(signature)
+}
+
+// lets the server know that moved content was saved, so that it can be
+// removed. if it failed to save, the connection is simply dropped instead, and
+// the content stays where it was.
+async fn remove_operation(config: &Config, stream: &mut Stream, h3: &[u8]) -> Result<(), Error> {
+    let h4 = auth4move(config.psk(), h3);
+    stream.write_all(h4.as_bytes()).await?;
+    stream.flush().await?;
+    let mut rbuf = [0; 33];
+    stream.read_exact(&mut rbuf).await?;
+    let h5 = &rbuf[..32];
+    let removed = rbuf[32];
+    let wh5 = auth5move(config.psk(), h4.as_bytes(), removed);
+    if wh5.as_bytes().ct_eq(h5).to_u8() != 1 {
+        return Err(Error::Auth);
+    }
+    // someone else got to it first. what we have is still a perfectly good copy.
+    if removed == 0 && isatty(true) {
+        eprintln!("the content was already gone from the clipboard");
+    }
+    Ok(())
+}
+
+// content that can't be let out before its signature has been checked, kept in
+// a temporary file that only we can read in the meantime.
+struct Spool {
+    tmp: PathBuf,
+    file: Option<BufWriter<File>>,
+}
+
+impl Spool {
+    fn create() -> io::Result<Self> {
+        let tmp = env::temp_dir().join(format!(".klip-{:016x}.tmp", rand_core::OsRng.next_u64()));
+        let file = platform::fs::create_private(&tmp)?;
+        Ok(Self {
+            tmp,
+            file: Some(BufWriter::new(file)),
+        })
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file
+            .as_mut()
+            .expect("the spool is always open until it is dropped")
+            .write_all(data)
+    }
+
+    // everything that was written, from the start. the spool has to outlive
+    // what is returned, since it is removed along with it.
+    fn reader(&mut self) -> io::Result<File> {
+        let file = self
+            .file
+            .as_mut()
+            .expect("the spool is always open until it is dropped");
+        file.flush()?;
+        let mut file = file.get_ref().try_clone()?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        drop(self.file.take());
+        _ = fs::remove_file(&self.tmp);
+    }
+}
+
+// a command that is handed the content on its standard input. it is only
+// started once the signature has been checked, so it never sees anything that
+// wasn't.
+struct Exec {
+    cmd: Command,
+    spool: Spool,
+}
+
+impl Exec {
+    fn create(command: &str, header: &Header) -> Result<Self, Error> {
+        let mut cmd = platform::process::shell(command);
+        if let Some(name) = &header.name {
+            cmd.env("KLIP_NAME", name);
+        }
+        if let Some(content_type) = &header.content_type {
+            cmd.env("KLIP_TYPE", content_type);
+        }
+        Ok(Self {
+            cmd,
+            spool: Spool::create()?,
+        })
+    }
+
+    fn run(mut self) -> Result<(), Error> {
+        let status = self.cmd.stdin(self.spool.reader()?).status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("the command failed ({status})")).into());
+        }
+        Ok(())
+    }
+}
+
+// where pasted content ends up.
+enum Sink {
+    Stdout(Spool),
+    File(Output),
+    Archive(Unpacker),
+    Exec(Exec),
+}
+
+impl Sink {
+    fn create(options: &PasteOptions, header: &Header) -> Result<Self, Error> {
+        if let Some(command) = &options.exec {
+            return Ok(Self::Exec(Exec::create(command, header)?));
+        }
+        if options.recursive {
+            if header.content_type.as_deref() != Some(archive::CONTENT_TYPE) {
+                return Err(Error::NotArchive);
+            }
+            let dest = options.output.as_deref().unwrap_or_else(|| Path::new("."));
+            return Ok(Self::Archive(Unpacker::new(
+                dest,
+                header.name.as_deref(),
+                options.force,
+            )?));
+        }
+        Ok(match &options.output {
+            Some(dest) => Self::File(Output::create(dest, header, options.force)?),
+            None => Self::Stdout(Spool::create()?),
+        })
+    }
+
+    fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        match self {
+            Self::Stdout(spool) => spool.write_all(data)?,
+            Self::File(output) => output.write_all(data)?,
+            Self::Archive(unpacker) => unpacker.write_all(data)?,
+            Self::Exec(exec) => exec.spool.write_all(data)?,
+        }
+        Ok(())
+    }
+
+    fn commit(self) -> Result<(), Error> {
+        match self {
+            Self::Stdout(mut spool) => {
+                let mut stdout = io::stdout().lock();
+                io::copy(&mut spool.reader()?, &mut stdout)?;
+                stdout.flush()?;
+            }
+            Self::File(output) => {
+                let path = output.commit()?;
+                if isatty(true) {
+                    eprintln!("Saved to '{}'", path.display());
+                }
+            }
+            Self::Archive(unpacker) => {
+                let path = unpacker.commit()?;
+                if isatty(true) {
+                    eprintln!("Extracted to '{}'", path.display());
+                }
             }
-        })?;
-    let encrypt_sk_id = {
-        let c = &ciphertext_with_encrypt_sk_id_and_nonce[..8];
-        [c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]
+            Self::Exec(exec) => exec.run()?,
+        }
+        Ok(())
+    }
+}
+
+// reads the envelope, and writes the content out wherever `options` say.
+async fn read_content(
+    config: &Config,
+    stream: &mut Stream,
+    envelope_len: u64,
+    signature: &[u8; 64],
+    options: &PasteOptions,
+) -> Result<(), Error> {
+    let header = read_envelope_header(stream).await?;
+    let mut remaining = envelope_len
+        .checked_sub(header.len() as u64)
+        .ok_or(Error::Corrupt)?;
+    let mut opener = Opener::new(config, config.sign_pks(), &header)?;
+    let mut body = vec![0; CHUNK_SIZE + TAG_LEN + SIGNATURE_LEN];
+    let mut block = vec![0; CHUNK_SIZE];
+    let mut header = None;
+    let mut sink = None;
+    // the header and every chunk are checked against the signer before they
+    // are opened, but nothing is let out of the sink until the signature over
+    // the whole envelope has been checked too, once the last chunk is in. a
+    // truncated envelope doesn't leave anything behind either.
+    loop {
+        let mut word = [0; 4];
+        stream.read_exact(&mut word).await?;
+        let (len, last) = envelope::frame_len(word)?;
+        let frame_len = (word.len() + len + TAG_LEN + SIGNATURE_LEN) as u64;
+        if frame_len > remaining {
+            return Err(Error::Corrupt);
+        }
+        remaining -= frame_len;
+        let body = &mut body[..len + TAG_LEN + SIGNATURE_LEN];
+        stream.read_exact(body).await?;
+        let chunk = opener.open(word, body)?;
+        let Some(Header { compression, .. }) = header else {
+            // there is always content after the header, even if it is empty.
+            if last {
+                return Err(Error::Corrupt);
+            }
+            let parsed = Header::from_bytes(chunk)?;
+            if !options.info {
+                sink = Some(Sink::create(options, &parsed)?);
+            }
+            header = Some(parsed);
+            continue;
+        };
+        // the content still has to be read in full to check the signature.
+        if let Some(sink) = &mut sink {
+            sink.write_all(compression.decompress(chunk, &mut block)?)?;
+        }
+        if last {
+            break;
+        }
+    }
+    if remaining != 0 {
+        return Err(Error::Corrupt);
+    }
+    let signer = opener.finish(signature)?;
+    if options.info {
+        let header = header.expect("the header comes before the last chunk");
+        return print_info(&header, signer);
+    }
+    sink.expect("the header comes before the last chunk")
+        .commit()?;
+    if isatty(true) {
+        eprintln!("Signed by '{signer}'");
+    }
+    Ok(())
+}
+
+fn print_info(header: &Header, signer: &str) -> Result<(), Error> {
+    let mut stdout = io::stdout().lock();
+    if let Some(name) = &header.name {
+        writeln!(stdout, "name\t{name}")?;
+    }
+    if let Some(content_type) = &header.content_type {
+        writeln!(stdout, "type\t{content_type}")?;
+    }
+    if let Some(size) = header.size {
+        writeln!(stdout, "size\t{size}")?;
+    }
+    if let Some(mode) = header.mode {
+        writeln!(stdout, "mode\t{mode:04o}")?;
+    }
+    let compression = match header.compression {
+        Compression::None => "none",
+        Compression::Lz4 => "lz4",
     };
-    if encrypt_sk_id
-        .ct_eq(&config.encrypt_sk_id().to_le_bytes())
-        .to_u8()
-        != 1
-    {
-        let w_encrypt_sk = config.encrypt_sk_id();
-        let encrypt_sk = u64::from_le_bytes(encrypt_sk_id);
-        return Err(Error::SecretKeyIDMismatch {
-            expected: w_encrypt_sk,
-            actual: encrypt_sk,
-        });
+    writeln!(stdout, "compression\t{compression}")?;
+    writeln!(stdout, "signer\t{signer}")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[allow(clippy::cast_possible_truncation)]
+async fn history_operation(
+    config: &Config,
+    stream: &mut Stream,
+    h1: &[u8],
+    slot: &[u8; 32],
+) -> Result<(), Error> {
+    let opcode = b'H';
+    let h2 = auth2history(config.psk(), h1, opcode, slot);
+    stream.write_all(&[opcode]).await?;
+    stream.write_all(slot).await?;
+    stream.write_all(h2.as_bytes()).await?;
+    stream.flush().await?;
+    let mut h3 = [0; 32];
+    stream.read_exact(&mut h3).await.map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Error::Rejected
+        } else {
+            e.into()
+        }
+    })?;
+    let mut entries = Vec::new();
+    stream.set_timeout(config.data_timeout());
+    stream.read_to_end(&mut entries).await?;
+    let wh3 = auth3history(config.psk(), h2.as_bytes(), &entries);
+    if wh3.as_bytes().ct_eq(&h3).to_u8() != 1 {
+        return Err(Error::Auth);
     }
-    config.sign_pk().verify_strict(
-        &ciphertext_with_encrypt_sk_id_and_nonce[..],
-        &ed25519::Signature::from_bytes(&signature)?,
-    )?;
-    let nonce = &ciphertext_with_encrypt_sk_id_and_nonce[8..32];
-    let mut cipher = xchacha20::XChaCha20::new(
-        &config.encrypt_sk(),
-        nonce
-            .try_into()
-            .expect("8..32 doesn't span 24 bytes. math has died."),
-    );
-    let content = &mut ciphertext_with_encrypt_sk_id_and_nonce[32..];
-    cipher.apply_keystream(&mut content[..]);
-    io::stdout().lock().write_all(content)?;
-    io::stdout().lock().flush()?;
+    let Some((count, entries)) = entries.split_first_chunk::<8>() else {
+        return Err(Error::Short);
+    };
+    if entries.len() as u64 != u64::from_le_bytes(*count).saturating_mul(16) {
+        return Err(Error::Short);
+    }
+    if entries.is_empty() && isatty(true) {
+        eprintln!("the clipboard is empty");
+    }
+    let mut stdout = io::stdout().lock();
+    for (index, entry) in entries.chunks(16).enumerate() {
+        let mut ts = [0; 8];
+        ts.copy_from_slice(&entry[..8]);
+        let mut len = [0; 8];
+        len.copy_from_slice(&entry[8..]);
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(u64::from_le_bytes(ts)))
+            .unwrap_or_default();
+        let size = envelope::plaintext_len(u64::from_le_bytes(len));
+        writeln!(
+            stdout,
+            "{index}\t{}\t{}",
+            human_age(elapsed),
+            human_size(size)
+        )?;
+    }
+    stdout.flush()?;
     Ok(())
 }
 
-pub async fn run(config: Config, is_copy: bool, is_move: bool) -> Result<(), Error> {
+async fn status_operation(
+    config: &Config,
+    stream: &mut Stream,
+    h1: &[u8],
+    slot: &[u8; 32],
+    options: &StatusOptions,
+) -> Result<(), Error> {
+    let opcode = b'T';
+    let index = options.index.to_le_bytes();
+    let h2 = auth2get(config.psk(), h1, opcode, slot, &index);
+    stream.write_all(&[opcode]).await?;
+    stream.write_all(slot).await?;
+    stream.write_all(&index).await?;
+    stream.write_all(h2.as_bytes()).await?;
+    stream.flush().await?;
+    let mut rbuf = [0; 80];
+    stream.read_exact(&mut rbuf).await.map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Error::Rejected
+        } else {
+            e.into()
+        }
+    })?;
+    let h3 = &rbuf[..32];
+    let stat = &rbuf[32..80];
+    let wh3 = auth3stat(config.psk(), h2.as_bytes(), stat);
+    if wh3.as_bytes().ct_eq(h3).to_u8() != 1 {
+        return Err(Error::Auth);
+    }
+    let ts = u64::from_le_bytes(stat[..8].try_into().expect("0..8 doesn't span 8 bytes"));
+    let size = u64::from_le_bytes(stat[8..16].try_into().expect("8..16 doesn't span 8 bytes"));
+    let signer = &stat[16..48];
+    if ts == 0 {
+        return Err(Error::Empty);
+    }
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH + Duration::from_secs(ts))
+        .unwrap_or_default();
+    let mut signer_pk = [0; 64];
+    hex(signer, &mut signer_pk);
+    let signer_pk = std::str::from_utf8(&signer_pk).expect("hex is ASCII");
+    // the signature can't be checked without the content, so this is only as
+    // good as the server's word. it did check it on store though.
+    let name = config
+        .sign_pks()
+        .iter()
+        .find(|(_, sign_pk)| sign_pk.as_bytes() == signer)
+        .map(|(name, _)| name.as_str());
+    let mut stdout = io::stdout().lock();
+    if options.json {
+        let name = name.map_or_else(|| "null".to_owned(), json_string);
+        writeln!(
+            stdout,
+            "{{\"timestamp\":{ts},\"age\":{},\"size\":{size},\"signer\":{name},\
+             \"signer_pk\":\"{signer_pk}\"}}",
+            elapsed.as_secs()
+        )?;
+    } else {
+        writeln!(stdout, "timestamp\t{ts}")?;
+        writeln!(stdout, "age\t{}", human_age(elapsed))?;
+        writeln!(stdout, "size\t{}", human_size(size))?;
+        writeln!(stdout, "signer\t{}", name.unwrap_or(signer_pk))?;
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+// connects to the server and goes through the handshake, which gives the
+// version that was agreed on and h1.
+async fn connect(config: &Config) -> Result<(Stream, u8, [u8; 32]), Error> {
     let psk = config.psk();
     let conn = TcpStream::connect_timeout(&config.connect(), config.timeout())?;
     let s = tokio::net::TcpStream::from_std(conn)?;
     let mut stream = Stream::new(s);
-    let mut r = [0; 32];
-    let mut rng = rand_core::OsRng;
-    rng.fill_bytes(&mut r);
-    let h0 = auth0(psk, DEFAULT_CLIENT_VERSION, &r);
-    stream.write_all(&[DEFAULT_CLIENT_VERSION]).await?;
+    let (ephemeral, r) = Ephemeral::generate();
+    let h0 = auth0(
+        psk,
+        &[crate::MIN_PROTOCOL_VERSION, DEFAULT_CLIENT_VERSION],
+        &r,
+    );
+    stream
+        .write_all(&[crate::MIN_PROTOCOL_VERSION, DEFAULT_CLIENT_VERSION])
+        .await?;
     stream.write_all(&r).await?;
     stream.write_all(h0.as_bytes()).await?;
     stream.flush().await?;
@@ -193,29 +783,215 @@ pub async fn run(config: Config, is_copy: bool, is_move: bool) -> Result<(), Err
         if rbuf.iter().position(|&b| b == 0).is_some_and(|p| p < 2) {
             io::Error::new(
                 io::ErrorKind::ConnectionRefused,
-                "the server rejected the connection - check that it is running the same klip \
-                 version or retry later",
+                "the server rejected the connection - check that it is running a compatible \
+                 klip version or retry later",
             )
             .into()
         } else {
             Error::ProtocolUnsupported
         }
     })?;
-    if rbuf[0] != DEFAULT_CLIENT_VERSION {
+    let version = rbuf[0];
+    let r2 = &rbuf[1..33];
+    let h1 = &rbuf[33..65];
+    let wh1 = auth1(psk, version, h0.as_bytes(), r2);
+    if wh1.as_bytes().ct_eq(h1).to_u8() != 1 {
+        return Err(Error::Auth);
+    }
+    // the server answers with its own newest version if we have none in
+    // common. since h1 covers it, this can be trusted.
+    if !(crate::MIN_PROTOCOL_VERSION..=DEFAULT_CLIENT_VERSION).contains(&version) {
         return Err(Error::IncompatibleVersions {
             client: DEFAULT_CLIENT_VERSION,
-            server: rbuf[0],
+            server: version,
         });
     }
-    let r2 = &rbuf[1..33];
-    let h1 = &rbuf[33..65];
-    let wh1 = auth1(psk, DEFAULT_CLIENT_VERSION, h0.as_bytes(), r2);
-    if wh1.as_bytes().ct_eq(h1).to_u8() != 1 {
+    // the version can't have been tampered with, so this is only ever the case
+    // with a server that doesn't know any better.
+    if version >= SESSION_VERSION {
+        stream.start_session(ephemeral.agree(r2, h1, true)?);
+    }
+    Ok((
+        stream,
+        version,
+        h1.try_into().expect("33..65 doesn't span 32 bytes"),
+    ))
+}
+
+// returns the signature of the newest entry, or zeros if there is none, once
+// it is no longer `seen`.
+async fn watch_operation(
+    config: &Config,
+    stream: &mut Stream,
+    h1: &[u8],
+    slot: &[u8; 32],
+    seen: &[u8; 64],
+) -> Result<[u8; 64], Error> {
+    let opcode = b'W';
+    let h2 = auth2watch(config.psk(), h1, opcode, slot, seen);
+    stream.write_all(&[opcode]).await?;
+    stream.write_all(slot).await?;
+    stream.write_all(h2.as_bytes()).await?;
+    stream.write_all(seen).await?;
+    stream.flush().await?;
+    // the server only answers once something changed, or after a while.
+    stream.set_timeout(crate::WATCH_INTERVAL + config.timeout());
+    let mut rbuf = [0; 104];
+    stream.read_exact(&mut rbuf).await.map_err(|e| {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            Error::Rejected
+        } else {
+            e.into()
+        }
+    })?;
+    let h3 = &rbuf[..32];
+    let ts = &rbuf[32..40];
+    let signature: [u8; 64] = rbuf[40..104]
+        .try_into()
+        .expect("40..104 doesn't span 64 bytes");
+    let wh3 = auth3get(config.psk(), h2.as_bytes(), ts, &signature);
+    if wh3.as_bytes().ct_eq(h3).to_u8() != 1 {
         return Err(Error::Auth);
     }
-    if is_copy {
-        copy_operation(&config, &mut stream, h1).await
-    } else {
-        paste_operation(&config, &mut stream, h1, is_move).await
+    Ok(signature)
+}
+
+async fn watch_once(config: &Config, slot: &[u8; 32], seen: &[u8; 64]) -> Result<[u8; 64], Error> {
+    let (mut stream, version, h1) = connect(config).await?;
+    if version < WATCH_VERSION.max(envelope::FORMAT_VERSION) {
+        return Err(Error::ProtocolUnsupported);
+    }
+    watch_operation(config, &mut stream, &h1, slot, seen).await
+}
+
+async fn paste_once(
+    config: &Config,
+    slot: &[u8; 32],
+    options: &PasteOptions,
+) -> Result<[u8; 64], Error> {
+    let (mut stream, _, h1) = connect(config).await?;
+    paste_operation(config, &mut stream, &h1, slot, false, options).await
+}
+
+// pastes every new entry in the slot, for as long as we're left running.
+async fn watch(config: &Config, slot: &str, options: &PasteOptions) -> Result<(), Error> {
+    let slot = config.slot_id(slot, options.received);
+    // this is never a valid signature, so the first answer comes right away,
+    // with whatever is there already. that isn't new, so it isn't pasted.
+    let mut seen = [0xff; 64];
+    let mut pasted = VecDeque::with_capacity(WATCH_REMEMBER);
+    let mut remember = |signature| {
+        if pasted.contains(&signature) {
+            return false;
+        }
+        if pasted.len() == WATCH_REMEMBER {
+            pasted.pop_front();
+        }
+        pasted.push_back(signature);
+        true
+    };
+    let mut retry = WATCH_RETRY_MIN;
+    loop {
+        // the server going away for a bit, or being busy, is no reason to stop
+        // watching. only what won't get better on its own is.
+        let latest = match watch_once(config, &slot, &seen).await {
+            Ok(latest) => {
+                retry = WATCH_RETRY_MIN;
+                latest
+            }
+            Err(
+                e @ (Error::Auth | Error::ProtocolUnsupported | Error::IncompatibleVersions { .. }),
+            ) => return Err(e),
+            Err(e) => {
+                eprintln!("error: {e} (retrying in {}s)", retry.as_secs());
+                tokio::time::sleep(retry).await;
+                retry = (retry * 2).min(WATCH_RETRY_MAX);
+                continue;
+            }
+        };
+        // the newest entry changes back to an older one when it is moved out,
+        // which shouldn't be pasted a second time.
+        if seen == [0xff; 64] {
+            remember(latest);
+        } else if latest != [0; 64] && remember(latest) {
+            // the watch goes on even if a single entry can't be pasted. by then,
+            // the newest entry may well be an even newer one.
+            match paste_once(config, &slot, options).await {
+                Ok(signature) => _ = remember(signature),
+                Err(e) => eprintln!("error: {e}"),
+            }
+        }
+        seen = latest;
+    }
+}
+
+pub async fn run(config: Config, slot: &str, op: Operation) -> Result<(), Error> {
+    // problems with local files are better caught before connecting.
+    let mut input = None;
+    match &op {
+        Operation::Copy(options) => input = Some(Input::open(options)?),
+        Operation::Paste(options) | Operation::Move(options) | Operation::Watch(options) => {
+            if options.recursive {
+                let dest = options.output.as_deref().unwrap_or_else(|| Path::new("."));
+                if !dest.is_dir() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotADirectory,
+                        format!("{}: not a directory", dest.display()),
+                    )
+                    .into());
+                }
+            } else if let Some(dest) = &options.output {
+                Output::check(dest, options.force)?;
+            }
+        }
+        Operation::History(_) | Operation::Status(_) => {}
+    }
+    if let Operation::Watch(options) = &op {
+        return watch(&config, slot, options).await;
+    }
+    let (mut stream, version, h1) = connect(&config).await?;
+    let h1 = &h1[..];
+    // older servers would misread the envelopes we send, and only have ones we
+    // can't read.
+    if matches!(
+        op,
+        Operation::Copy(_) | Operation::Paste(_) | Operation::Move(_)
+    ) && version < envelope::FORMAT_VERSION
+    {
+        return Err(Error::ProtocolUnsupported);
+    }
+    match op {
+        Operation::Copy(options) => {
+            let slot = config.slot_id(slot, !options.to.is_empty());
+            let input = input.expect("input is opened for copies");
+            copy_operation(&config, &mut stream, h1, &slot, &options, input).await
+        }
+        Operation::Paste(options) => {
+            let slot = config.slot_id(slot, options.received);
+            paste_operation(&config, &mut stream, h1, &slot, false, &options)
+                .await
+                .map(drop)
+        }
+        Operation::Move(options) => {
+            if version < MOVE_VERSION {
+                return Err(Error::ProtocolUnsupported);
+            }
+            let slot = config.slot_id(slot, options.received);
+            paste_operation(&config, &mut stream, h1, &slot, true, &options)
+                .await
+                .map(drop)
+        }
+        Operation::History(options) => {
+            let slot = config.slot_id(slot, options.received);
+            history_operation(&config, &mut stream, h1, &slot).await
+        }
+        Operation::Status(options) => {
+            if version < STAT_VERSION {
+                return Err(Error::ProtocolUnsupported);
+            }
+            let slot = config.slot_id(slot, options.received);
+            status_operation(&config, &mut stream, h1, &slot, &options).await
+        }
+        Operation::Watch(_) => unreachable!(),
     }
 }
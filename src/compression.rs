@@ -0,0 +1,71 @@
+use crate::{envelope::CHUNK_SIZE, error::Error};
+
+// content is compressed a block at a time, before it is encrypted. blocks are
+// small enough that even one that doesn't compress at all still fits in a
+// chunk, after the byte that says whether it was compressed.
+pub const BLOCK_SIZE: usize = CHUNK_SIZE - CHUNK_SIZE / 256 - 16;
+const _: () = assert!(lz4::compress_bound(BLOCK_SIZE) < CHUNK_SIZE);
+const RAW: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+}
+
+impl Compression {
+    pub const fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Lz4 => 1,
+        }
+    }
+
+    pub const fn from_id(id: u8) -> Result<Self, Error> {
+        match id {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Lz4),
+            _ => Err(Error::UnknownCompression(id)),
+        }
+    }
+
+    // compresses `block` into `chunk`, which must be CHUNK_SIZE bytes long, and
+    // returns the length of the result. blocks that LZ4 doesn't shrink, like
+    // those of data that is already compressed or encrypted, are kept as they
+    // are, so that mixed content only compresses where it helps.
+    pub fn compress(self, block: &[u8], chunk: &mut [u8]) -> usize {
+        match self {
+            Self::None => {
+                chunk[..block.len()].copy_from_slice(block);
+                block.len()
+            }
+            Self::Lz4 => {
+                let len = lz4::compress(block, &mut chunk[1..]);
+                if len < block.len() {
+                    chunk[0] = COMPRESSED;
+                    len + 1
+                } else {
+                    chunk[0] = RAW;
+                    chunk[1..=block.len()].copy_from_slice(block);
+                    block.len() + 1
+                }
+            }
+        }
+    }
+
+    // `block` must be CHUNK_SIZE bytes long.
+    pub fn decompress<'a>(self, chunk: &'a [u8], block: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        match self {
+            Self::None => Ok(chunk),
+            Self::Lz4 => match chunk.split_first() {
+                Some((&RAW, raw)) => Ok(raw),
+                Some((&COMPRESSED, chunk)) => {
+                    let len = lz4::decompress(chunk, block).map_err(|_| Error::Corrupt)?;
+                    Ok(&block[..len])
+                }
+                _ => Err(Error::Corrupt),
+            },
+        }
+    }
+}
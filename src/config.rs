@@ -1,9 +1,16 @@
 use crate::{
-    cli::{Cli, Command},
+    cidr::Cidr,
+    cli::{Cli, Command, ServerArgs},
     error::Error,
+    log::{Format, Level},
 };
+use clap::ValueEnum;
 use ed25519::{SigningKey, VerifyingKey};
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 #[allow(clippy::module_name_repetitions)]
 pub struct TomlConfig {
@@ -31,6 +38,60 @@ impl TomlConfig {
         }
     }
 
+    pub fn metrics_listen(&self) -> Result<Option<SocketAddr>, Error> {
+        match self.table.get("metrics_listen") {
+            Some(toml::Value::String(v)) => v
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::InvalidField("metrics_listen")),
+            Some(_) => Err(Error::InvalidField("metrics_listen")),
+            None => Ok(None),
+        }
+    }
+
+    pub fn allow(&self) -> Result<Vec<Cidr>, Error> {
+        self.cidrs("allow")
+    }
+
+    pub fn deny(&self) -> Result<Vec<Cidr>, Error> {
+        self.cidrs("deny")
+    }
+
+    fn cidrs(&self, field: &'static str) -> Result<Vec<Cidr>, Error> {
+        match self.table.get(field) {
+            Some(toml::Value::Array(cidrs)) => cidrs
+                .iter()
+                .map(|v| match v {
+                    toml::Value::String(v) => v.parse().ok(),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .ok_or(Error::InvalidField(field)),
+            Some(_) => Err(Error::InvalidField(field)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn log_level(&self) -> Result<Option<Level>, Error> {
+        match self.table.get("log_level") {
+            Some(toml::Value::String(v)) => Level::from_str(v, true)
+                .map(Some)
+                .map_err(|_| Error::InvalidField("log_level")),
+            Some(_) => Err(Error::InvalidField("log_level")),
+            None => Ok(None),
+        }
+    }
+
+    pub fn log_format(&self) -> Result<Option<Format>, Error> {
+        match self.table.get("log_format") {
+            Some(toml::Value::String(v)) => Format::from_str(v, true)
+                .map(Some)
+                .map_err(|_| Error::InvalidField("log_format")),
+            Some(_) => Err(Error::InvalidField("log_format")),
+            None => Ok(None),
+        }
+    }
+
     pub fn encrypt_sk(&self) -> Result<[u8; 32], Error> {
         if let Some(toml::Value::String(v)) = self.table.get("encrypt_sk") {
             let mut buf = [0; 32];
@@ -46,6 +107,10 @@ impl TomlConfig {
         if let Some(toml::Value::String(v)) = self.table.get("encrypt_sk_id") {
             crate::util::from_hex(v, &mut buf)
                 .map_err(|()| Error::InvalidField("encrypt_sk_id"))?;
+            // reserved for content sent to recipients.
+            if u64::from_le_bytes(buf) == crate::envelope::RECIPIENTS {
+                return Err(Error::InvalidField("encrypt_sk_id"));
+            }
         } else {
             let encrypt_sk = self.encrypt_sk()?;
             let mut hasher = blake2b::Params::new()
@@ -58,6 +123,16 @@ impl TomlConfig {
         Ok(u64::from_le_bytes(buf))
     }
 
+    pub fn recipient_sk(&self) -> Result<Option<[u8; 32]>, Error> {
+        if let Some(toml::Value::String(v)) = self.table.get("recipient_sk") {
+            let mut buf = [0; 32];
+            crate::util::from_hex(v, &mut buf).map_err(|()| Error::InvalidField("recipient_sk"))?;
+            Ok(Some(buf))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn psk(&self) -> Result<[u8; 32], Error> {
         if let Some(toml::Value::String(v)) = self.table.get("psk") {
             let mut buf = [0; 32];
@@ -70,14 +145,59 @@ impl TomlConfig {
 
     pub fn sign_pk(&self) -> Result<VerifyingKey, Error> {
         if let Some(toml::Value::String(v)) = self.table.get("sign_pk") {
-            let mut buf = [0; 32];
-            crate::util::from_hex(v, &mut buf).map_err(|()| Error::InvalidField("sign_pk"))?;
-            VerifyingKey::from_bytes(&buf).map_err(|_| Error::InvalidField("sign_pk"))
+            parse_sign_pk(v).ok_or(Error::InvalidField("sign_pk"))
         } else {
             Err(Error::MissingField("sign_pk"))
         }
     }
 
+    // the top-level `sign_pk` is known as "default", alongside everything in
+    // the `[sign_pks]` table. revoked keys are dropped, even if they are still
+    // listed under some name.
+    pub fn sign_pks(&self) -> Result<Vec<(String, VerifyingKey)>, Error> {
+        let mut sign_pks = Vec::new();
+        if self.table.contains_key("sign_pk") {
+            sign_pks.push((DEFAULT_SIGN_PK.to_owned(), self.sign_pk()?));
+        }
+        match self.table.get("sign_pks") {
+            Some(toml::Value::Table(table)) => {
+                for (name, v) in table {
+                    let toml::Value::String(v) = v else {
+                        return Err(Error::InvalidField("sign_pks"));
+                    };
+                    let sign_pk = parse_sign_pk(v).ok_or(Error::InvalidField("sign_pks"))?;
+                    if name.is_empty() || sign_pks.iter().any(|(n, _)| n == name) {
+                        return Err(Error::InvalidField("sign_pks"));
+                    }
+                    sign_pks.push((name.clone(), sign_pk));
+                }
+            }
+            Some(_) => return Err(Error::InvalidField("sign_pks")),
+            None => {}
+        }
+        if sign_pks.is_empty() {
+            return Err(Error::MissingField("sign_pk"));
+        }
+        let revoked = self.revoked_sign_pks()?;
+        sign_pks.retain(|(_, sign_pk)| !revoked.contains(sign_pk));
+        Ok(sign_pks)
+    }
+
+    pub fn revoked_sign_pks(&self) -> Result<Vec<VerifyingKey>, Error> {
+        match self.table.get("revoked_sign_pks") {
+            Some(toml::Value::Array(revoked)) => revoked
+                .iter()
+                .map(|v| match v {
+                    toml::Value::String(v) => parse_sign_pk(v),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .ok_or(Error::InvalidField("revoked_sign_pks")),
+            Some(_) => Err(Error::InvalidField("revoked_sign_pks")),
+            None => Ok(Vec::new()),
+        }
+    }
+
     pub fn sign_sk(&self) -> Result<SigningKey, Error> {
         if let Some(toml::Value::String(v)) = self.table.get("sign_sk") {
             let mut buf = [0; 32];
@@ -100,21 +220,285 @@ impl TomlConfig {
             crate::DEFAULT_TTL
         }
     }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub fn max_ttl(&self) -> Duration {
+        if let Some(toml::Value::Integer(v)) = self.table.get("max_ttl") {
+            if *v > 0 {
+                Duration::from_secs(*v as u64)
+            } else {
+                self.ttl()
+            }
+        } else {
+            self.ttl()
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn history(&self) -> usize {
+        if let Some(toml::Value::Integer(v)) = self.table.get("history") {
+            if *v > 0 {
+                *v as usize
+            } else {
+                crate::DEFAULT_HISTORY
+            }
+        } else {
+            crate::DEFAULT_HISTORY
+        }
+    }
+
+    pub fn storage_dir(&self) -> Option<PathBuf> {
+        if let Some(toml::Value::String(v)) = self.table.get("storage_dir") {
+            Some(PathBuf::from(v))
+        } else {
+            None
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    pub fn max_len_mb(&self) -> Option<u64> {
+        if let Some(toml::Value::Integer(v)) = self.table.get("max_len_mb") {
+            (*v >= 0).then_some(*v as u64)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(&self) -> Result<String, Error> {
+        match self.table.get("name") {
+            Some(toml::Value::String(v)) if is_valid_tenant_name(v) => Ok(v.clone()),
+            Some(_) => Err(Error::InvalidField("name")),
+            None => Err(Error::MissingField("name")),
+        }
+    }
+
+    // every `[[tenants]]` table inherits the top-level limits it doesn't set
+    // itself, as well as the revoked keys. the storage directory is the
+    // exception, since sharing it would mix up the tenants' content.
+    pub fn tenants(&self) -> Result<Vec<Self>, Error> {
+        let Some(tenants) = self.table.get("tenants") else {
+            return Ok(Vec::new());
+        };
+        let toml::Value::Array(tenants) = tenants else {
+            return Err(Error::InvalidField("tenants"));
+        };
+        let mut inherited = self.table.clone();
+        for key in [
+            "tenants",
+            "name",
+            "psk",
+            "sign_pk",
+            "sign_pks",
+            "storage_dir",
+        ] {
+            inherited.remove(key);
+        }
+        tenants
+            .iter()
+            .map(|tenant| {
+                let toml::Value::Table(tenant) = tenant else {
+                    return Err(Error::InvalidField("tenants"));
+                };
+                let mut table = inherited.clone();
+                table.extend(tenant.clone());
+                Ok(Self::new(table))
+            })
+            .collect()
+    }
+}
+
+fn parse_sign_pk(v: &str) -> Option<VerifyingKey> {
+    let mut buf = [0; 32];
+    crate::util::from_hex(v, &mut buf).ok()?;
+    VerifyingKey::from_bytes(&buf).ok()
+}
+
+// the name reported for content signed with the top-level `sign_pk`.
+const DEFAULT_SIGN_PK: &str = "default";
+
+// tenant names double as directory names under `storage_dir`.
+fn is_valid_tenant_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && !name.starts_with('.')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+}
+
+// whether a tenant storing its content in `outer` would load, sweep or
+// overwrite what another tenant keeps in `inner`. a directory right under
+// `outer` that is named like a tenant is left alone, since slot files are named
+// after much longer slot ids, which is how the default tenant and the others
+// share `storage_dir`.
+fn shares_storage(outer: &Path, inner: &Path) -> bool {
+    let Ok(rest) = inner.strip_prefix(outer) else {
+        return false;
+    };
+    !matches!(
+        rest.components().next(),
+        Some(Component::Normal(name)) if name.to_str().is_some_and(is_valid_tenant_name)
+    )
+}
+
+// the name of the tenant made up of the top-level `psk` and `sign_pk`, if any.
+const DEFAULT_TENANT: &str = "default";
+
+pub struct Tenant {
+    name: String,
+    psk: [u8; 32],
+    sign_pks: Vec<(String, VerifyingKey)>,
+    max_len: u64,
+    max_ttl: Duration,
+    history: usize,
+    storage_dir: Option<PathBuf>,
+}
+
+impl std::fmt::Debug for Tenant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_secrets = f.alternate();
+        let mut s = f.debug_struct("Tenant");
+        s.field("name", &self.name)
+            .field("max_len", &self.max_len)
+            .field("max_ttl", &self.max_ttl)
+            .field("history", &self.history)
+            .field("storage_dir", &self.storage_dir);
+        if display_secrets {
+            s.field("psk", &self.psk);
+            s.field("sign_pks", &self.sign_pks);
+            s.finish()
+        } else {
+            s.finish_non_exhaustive()
+        }
+    }
+}
+
+impl Tenant {
+    fn new(
+        t: &TomlConfig,
+        name: String,
+        storage_dir: Option<PathBuf>,
+        args: &ServerArgs,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            name,
+            psk: t.psk()?,
+            sign_pks: t.sign_pks()?,
+            max_len: t
+                .max_len_mb()
+                .unwrap_or(args.max_len_mb)
+                .checked_mul(1024 * 1024)
+                .ok_or(Error::InvalidField("max_len_mb"))?,
+            max_ttl: t.max_ttl(),
+            history: t.history(),
+            storage_dir,
+        })
+    }
+
+    fn all(t: &TomlConfig, args: &ServerArgs) -> Result<Vec<Self>, Error> {
+        let mut tenants = Vec::new();
+        if t.table.contains_key("psk") {
+            tenants.push(Self::new(
+                t,
+                DEFAULT_TENANT.to_owned(),
+                t.storage_dir(),
+                args,
+            )?);
+        }
+        for tenant in t.tenants()? {
+            let name = tenant.name()?;
+            let storage_dir = tenant
+                .storage_dir()
+                .or_else(|| t.storage_dir().map(|dir| dir.join(&name)));
+            tenants.push(Self::new(&tenant, name, storage_dir, args)?);
+        }
+        if tenants.is_empty() {
+            return Err(Error::MissingField("psk"));
+        }
+        // the tenant is identified by its psk alone.
+        for (i, tenant) in tenants.iter().enumerate() {
+            if tenants[..i]
+                .iter()
+                .any(|other| other.name == tenant.name || other.psk == tenant.psk)
+            {
+                return Err(Error::InvalidField("tenants"));
+            }
+            if tenants[..i].iter().any(|other| {
+                let (Some(a), Some(b)) = (tenant.storage_dir(), other.storage_dir()) else {
+                    return false;
+                };
+                shares_storage(a, b) || shares_storage(b, a)
+            }) {
+                return Err(Error::InvalidField("storage_dir"));
+            }
+        }
+        Ok(tenants)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub const fn psk(&self) -> [u8; 32] {
+        self.psk
+    }
+
+    pub fn sign_pks(&self) -> &[(String, VerifyingKey)] {
+        &self.sign_pks
+    }
+
+    // content stays around after its key is revoked, but it shouldn't be
+    // served any longer.
+    pub fn is_trusted(&self, signer: &[u8; 32]) -> bool {
+        self.sign_pks
+            .iter()
+            .any(|(_, sign_pk)| sign_pk.as_bytes() == signer)
+    }
+
+    pub const fn max_len(&self) -> u64 {
+        self.max_len
+    }
+
+    pub const fn max_ttl(&self) -> Duration {
+        self.max_ttl
+    }
+
+    pub const fn history(&self) -> usize {
+        self.history
+    }
+
+    pub fn storage_dir(&self) -> Option<&Path> {
+        self.storage_dir.as_deref()
+    }
+
+    // `ts` is the time the content was received at, by the server's clock.
+    pub fn is_expired(&self, ts: u64) -> bool {
+        UNIX_EPOCH
+            .checked_add(Duration::from_secs(ts))
+            .and_then(|ts| SystemTime::now().duration_since(ts).ok())
+            .is_some_and(|elapsed| elapsed >= self.max_ttl)
+    }
 }
 
 pub struct Config {
     connect: SocketAddr,
     listen: SocketAddr,
+    metrics_listen: Option<SocketAddr>,
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+    log_level: Level,
+    log_format: Format,
     max_clients: usize,
-    max_len: u64,
-    encrypt_sk: [u8; 32],
+    encrypt_sk: Option<[u8; 32]>,
     encrypt_sk_id: u64,
+    recipient_sk: Option<[u8; 32]>,
     psk: [u8; 32],
-    sign_pk: VerifyingKey,
+    sign_pks: Vec<(String, VerifyingKey)>,
     sign_sk: SigningKey,
     timeout: Duration,
     data_timeout: Duration,
+    grace_period: Duration,
     ttl: Duration,
+    tenants: Vec<Tenant>,
     trusted_ip_count: usize,
 }
 
@@ -124,10 +508,15 @@ impl std::fmt::Debug for Config {
         let mut s = f.debug_struct("Config");
         s.field("connect", &self.connect)
             .field("listen", &self.listen)
+            .field("metrics_listen", &self.metrics_listen)
+            .field("allow", &self.allow)
+            .field("deny", &self.deny)
+            .field("log_level", &self.log_level)
+            .field("log_format", &self.log_format)
             .field("max_clients", &self.max_clients)
-            .field("max_len", &self.max_len)
             .field("timeout", &self.timeout)
             .field("data_timeout", &self.data_timeout)
+            .field("grace_period", &self.grace_period)
             .field("ttl", &self.ttl)
             .field("trusted_ip_count", &self.trusted_ip_count);
         if display_secrets {
@@ -139,11 +528,14 @@ impl std::fmt::Debug for Config {
                 "encrypt_sk_id",
                 &std::str::from_utf8(&out).expect("hex should be valid utf8"),
             );
+            s.field("recipient_sk", &self.recipient_sk);
             s.field("psk", &self.psk);
-            s.field("sign_pk", &self.sign_pk);
+            s.field("sign_pks", &self.sign_pks);
             s.field("sign_sk", &self.sign_sk);
+            s.field("tenants", &format_args!("{:#?}", self.tenants));
             s.finish()
         } else {
+            s.field("tenants", &self.tenants);
             s.finish_non_exhaustive()
         }
     }
@@ -151,31 +543,73 @@ impl std::fmt::Debug for Config {
 
 impl Config {
     pub fn new(t: &TomlConfig, c: &Cli) -> Result<Self, Error> {
+        let recipient_sk = if let Command::Serve(_) = c.subcommand {
+            None
+        } else {
+            t.recipient_sk()?
+        };
+        // a client that only ever receives content sent to its recipient key
+        // has no use for `encrypt_sk`.
+        let encrypt_sk = match c.subcommand {
+            Command::Serve(_) => None,
+            _ => match t.encrypt_sk() {
+                Ok(encrypt_sk) => Some(encrypt_sk),
+                Err(Error::MissingField(_)) if recipient_sk.is_some() => None,
+                Err(e) => return Err(e),
+            },
+        };
         Ok(Self {
             connect: t.connect(),
             listen: t.listen(),
-            max_len: if let Command::Serve(args) = c.subcommand {
-                args.max_len_mb * 1024 * 1024
+            metrics_listen: if let Command::Serve(_) = c.subcommand {
+                t.metrics_listen()?
             } else {
-                1
+                None
+            },
+            allow: if let Command::Serve(_) = c.subcommand {
+                t.allow()?
+            } else {
+                Vec::new()
+            },
+            deny: if let Command::Serve(_) = c.subcommand {
+                t.deny()?
+            } else {
+                Vec::new()
+            },
+            // the command line takes precedence, but the file should still
+            // make sense.
+            log_level: if let Command::Serve(args) = c.subcommand {
+                args.log_level.or(t.log_level()?).unwrap_or(Level::Info)
+            } else {
+                Level::Info
+            },
+            log_format: if let Command::Serve(args) = c.subcommand {
+                args.log_format.or(t.log_format()?).unwrap_or(Format::Text)
+            } else {
+                Format::Text
             },
             max_clients: if let Command::Serve(args) = c.subcommand {
                 args.max_clients.get()
             } else {
                 1
             },
-            encrypt_sk: if let Command::Serve(_) = c.subcommand {
+            encrypt_sk_id: if encrypt_sk.is_some() {
+                t.encrypt_sk_id()?
+            } else {
+                0
+            },
+            encrypt_sk,
+            recipient_sk,
+            psk: if let Command::Serve(_) = c.subcommand {
                 [0; 32]
             } else {
-                t.encrypt_sk()?
+                t.psk()?
             },
-            encrypt_sk_id: if let Command::Serve(_) = c.subcommand {
-                0
+            sign_pks: if let Command::Serve(_) = c.subcommand {
+                Vec::new()
             } else {
-                t.encrypt_sk_id()?
+                t.sign_pks()?
             },
-            psk: t.psk()?,
-            sign_pk: t.sign_pk()?,
             sign_sk: if let Command::Serve(_) = c.subcommand {
                 SigningKey::from_bytes(&[0; 32])
             } else {
@@ -191,7 +625,17 @@ impl Config {
             } else {
                 Duration::from_secs(3600)
             },
+            grace_period: if let Command::Serve(args) = c.subcommand {
+                Duration::from_secs(args.grace_period)
+            } else {
+                Duration::ZERO
+            },
             ttl: t.ttl(),
+            tenants: if let Command::Serve(args) = &c.subcommand {
+                Tenant::all(t, args)?
+            } else {
+                Vec::new()
+            },
             trusted_ip_count: if let Command::Serve(args) = c.subcommand {
                 match args.max_clients.get() / 10 {
                     0 => 1,
@@ -211,8 +655,8 @@ impl Config {
         self.data_timeout
     }
 
-    pub const fn max_len(&self) -> u64 {
-        self.max_len
+    pub const fn grace_period(&self) -> Duration {
+        self.grace_period
     }
 
     pub const fn trusted_ip_count(&self) -> usize {
@@ -231,6 +675,25 @@ impl Config {
         self.listen
     }
 
+    pub const fn metrics_listen(&self) -> Option<SocketAddr> {
+        self.metrics_listen
+    }
+
+    // an address has to be on the allow list, if there is one, and must not be
+    // on the deny list.
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip)))
+            && !self.deny.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    pub const fn log_level(&self) -> Level {
+        self.log_level
+    }
+
+    pub const fn log_format(&self) -> Format {
+        self.log_format
+    }
+
     pub const fn encrypt_sk_id(&self) -> u64 {
         self.encrypt_sk_id
     }
@@ -239,19 +702,49 @@ impl Config {
         self.connect
     }
 
-    pub const fn encrypt_sk(&self) -> [u8; 32] {
-        self.encrypt_sk
+    pub fn encrypt_sk(&self) -> Result<[u8; 32], Error> {
+        self.encrypt_sk.ok_or(Error::MissingField("encrypt_sk"))
+    }
+
+    pub const fn recipient_sk(&self) -> Option<[u8; 32]> {
+        self.recipient_sk
     }
 
     pub const fn ttl(&self) -> Duration {
         self.ttl
     }
 
-    pub const fn sign_pk(&self) -> VerifyingKey {
-        self.sign_pk
+    pub fn tenants(&self) -> &[Tenant] {
+        &self.tenants
+    }
+
+    pub fn sign_pks(&self) -> &[(String, VerifyingKey)] {
+        &self.sign_pks
     }
 
     pub const fn sign_sk(&self) -> &SigningKey {
         &self.sign_sk
     }
+
+    // the slot identifier is keyed with the encryption key rather than the psk,
+    // since the server knows the latter and could otherwise just run a
+    // dictionary over common slot names. recipients don't have the encryption
+    // key though, and share no other secret, so content sent to them has to
+    // make do with the psk, and the server can guess those slot names.
+    pub fn slot_id(&self, name: &str, for_recipients: bool) -> [u8; 32] {
+        let key = match self.encrypt_sk {
+            Some(encrypt_sk) if !for_recipients => encrypt_sk,
+            _ => self.psk,
+        };
+        let mut hasher = blake2b::Params::new()
+            .personal(crate::DOMAIN.as_bytes())
+            .salt(b"slot")
+            .key(&key)
+            .hash_length(32)
+            .to_state();
+        hasher.update(name.as_bytes());
+        let mut buf = [0; 32];
+        buf.copy_from_slice(hasher.finalize().as_bytes());
+        buf
+    }
 }
@@ -0,0 +1,515 @@
+use crate::{compression::Compression, config::Config, error::Error, DOMAIN};
+use crypto_common::{constant_time::ConstantTimeEq, erase::Erase};
+use curve25519::{montgomery::x25519, MontgomeryPoint};
+use ed25519::{Signature, SigningKey, VerifyingKey};
+use rand_core::RngCore;
+use xchacha20poly1305::{Tag, XChaCha20Poly1305};
+
+// content is split into chunks that are encrypted and authenticated one by
+// one, so that neither side has to hold all of it at once:
+//
+// envelope := format || ekid || n || hs || frame*
+// frame    := Len32(len | FINAL?) || ct || tag || sc
+// ct, tag  := XChaCha20-Poly1305(key=ek, nonce=n ^ Len(i), ad=Len32(...))(m)
+// sc       := Ed25519(Blake2b(salt="chk")(d))
+//
+// every chunk gets its own nonce, derived from its index. only the last chunk
+// carries the FINAL flag, which is what detects truncation. the signature
+// covers a digest of the whole envelope and is sent after it.
+//
+// hs is a signature over everything before it, by the same key. it is checked
+// before the first chunk is opened, so that nothing is decrypted for a signer
+// we don't know.
+//
+// that alone says nothing about the chunks, though: anyone who holds ek, or
+// who wraps a key for a recipient, can put their own chunks after a header
+// that was signed by someone else. sc is a signature over the digest d of the
+// envelope up to and including the tag of its chunk, by the key that signed
+// the header, and it is checked before the chunk is opened. every chunk is
+// then known to come from the signer, in the order they sent it.
+//
+// the first chunk is a header that describes the content and says how the rest
+// of it is to be read. it is encrypted like everything else:
+//
+// header := compression || field*
+// field  := tag || len || value
+//
+// with a one-byte tag and length. fields are optional, and ones with an unknown
+// tag are skipped.
+//
+// content sent to recipients is encrypted with a random key ck instead of ek.
+// ekid is then set to RECIPIENTS, and the header goes on with ck wrapped for
+// every one of them, before hs:
+//
+// recipients := Len32(count) || (epk_i || wk_i || wt_i)*
+// wk_i, wt_i := XChaCha20-Poly1305(key=kw_i, nonce=0, ad=n)(ck)
+// kw_i       := Blake2b(key=X25519(esk_i, pk_i), salt="wrap")(epk_i || pk_i)
+//
+// every wrapped key uses its own ephemeral keypair, so the zero nonce is never
+// reused. the recipients' public keys themselves are not part of it.
+//
+// the format comes first, so that clients can change how they write envelopes
+// without the server having to bump the protocol version for everyone. clients
+// that speak a version older than FORMAT_VERSION don't send it, and the server
+// fills in the format they used before storing their content. format 4 had no
+// chunk signatures, and isn't read anymore.
+pub const FORMAT: u8 = 5;
+// a single chunk without a tag, signed as is, which is what version 1 did.
+pub const FORMAT_UNCHUNKED: u8 = 0;
+// no header chunk, which is how clients wrote envelopes before version 4.
+pub const FORMAT_NO_HEADER: u8 = 1;
+// a header chunk, but no format of its own, which is what versions 4 to 6 did.
+pub const FORMAT_UNVERSIONED: u8 = 2;
+pub const FORMAT_VERSION: u8 = 7;
+// the length of ekid and n, which come after the format.
+pub const HEADER_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+pub const TAG_LEN: usize = xchacha20poly1305::TAG_SIZE;
+pub const CHUNK_SIZE: usize = 64 * 1024;
+const FINAL: u32 = 1 << 31;
+pub const RECIPIENTS: u64 = u64::MAX;
+pub const MAX_RECIPIENTS: usize = 256;
+const WRAPPED_KEY_LEN: usize = 32 + 32 + TAG_LEN;
+
+// the format of envelopes sent over `version` if the client doesn't say.
+pub const fn implicit_format(version: u8) -> Option<u8> {
+    match version {
+        ..2 => Some(FORMAT_UNCHUNKED),
+        2..4 => Some(FORMAT_NO_HEADER),
+        4..FORMAT_VERSION => Some(FORMAT_UNVERSIONED),
+        _ => None,
+    }
+}
+
+pub fn digest() -> blake2b::State {
+    blake2b::Params::new()
+        .personal(DOMAIN.as_bytes())
+        .salt(b"sig")
+        .to_state()
+}
+
+// what sc is a signature of, given the digest of the envelope so far.
+fn chunk_digest(digest: &blake2b::State) -> blake2b::Hash {
+    blake2b::Params::new()
+        .personal(DOMAIN.as_bytes())
+        .salt(b"chk")
+        .to_state()
+        .update(digest.finalize().as_bytes())
+        .finalize()
+}
+
+// what hs is a signature of, given everything before it.
+pub fn header_digest(header: &[u8]) -> blake2b::Hash {
+    blake2b::Params::new()
+        .personal(DOMAIN.as_bytes())
+        .salt(b"hdr")
+        .to_state()
+        .update(header)
+        .finalize()
+}
+
+// returns whichever of the keys made `signature` over the envelope digest.
+pub fn signer<'a>(
+    sign_pks: &'a [(String, VerifyingKey)],
+    digest: &[u8],
+    signature: &[u8; 64],
+) -> Result<&'a (String, VerifyingKey), Error> {
+    let signature = Signature::from_bytes(signature)?;
+    sign_pks
+        .iter()
+        .find(|(_, sign_pk)| sign_pk.verify_strict(digest, &signature).is_ok())
+        .ok_or(Error::Signature)
+}
+
+pub fn has_recipients(header: &[u8]) -> bool {
+    header.get(..8) == Some(&RECIPIENTS.to_le_bytes()[..])
+}
+
+// returns the length of the wrapped keys that follow the count.
+pub const fn recipients_len(count: [u8; 4]) -> Result<usize, Error> {
+    let count = u32::from_le_bytes(count) as usize;
+    if count == 0 || count > MAX_RECIPIENTS {
+        return Err(Error::Corrupt);
+    }
+    Ok(count * WRAPPED_KEY_LEN)
+}
+
+fn wrapping_key(shared: &[u8; 32], epk: &[u8; 32], pk: &[u8; 32]) -> XChaCha20Poly1305 {
+    let mut hasher = blake2b::Params::new()
+        .personal(DOMAIN.as_bytes())
+        .salt(b"wrap")
+        .key(shared)
+        .hash_length(32)
+        .to_state();
+    hasher.update(epk);
+    hasher.update(pk);
+    let hash = hasher.finalize();
+    XChaCha20Poly1305::new(hash.as_bytes().try_into().expect("hash should be 32 bytes"))
+}
+
+fn wrap(ck: &[u8; 32], nonce: &[u8; 24], pk: &[u8; 32]) -> Result<[u8; WRAPPED_KEY_LEN], Error> {
+    let mut esk = [0; 32];
+    rand_core::OsRng.fill_bytes(&mut esk);
+    let epk = MontgomeryPoint::mul_base_clamped(esk).to_bytes();
+    let mut shared = x25519(esk, *pk);
+    esk.erase();
+    // a public key of small order would make the wrapping key predictable.
+    if shared == [0; 32] {
+        return Err(Error::InvalidRecipient);
+    }
+    let aead = wrapping_key(&shared, &epk, pk);
+    shared.erase();
+    let mut wrapped = [0; WRAPPED_KEY_LEN];
+    wrapped[..32].copy_from_slice(&epk);
+    wrapped[32..64].copy_from_slice(ck);
+    let tag = aead.encrypt_in_place_detached(&[0; 24], nonce, &mut wrapped[32..64]);
+    wrapped[64..].copy_from_slice(&tag);
+    Ok(wrapped)
+}
+
+// tries every wrapped key in turn, since nothing says which one is ours.
+fn unwrap(recipients: &[u8], nonce: &[u8; 24], sk: &[u8; 32]) -> Result<[u8; 32], Error> {
+    let pk = MontgomeryPoint::mul_base_clamped(*sk).to_bytes();
+    for wrapped in recipients.as_chunks::<WRAPPED_KEY_LEN>().0 {
+        let epk: [u8; 32] = wrapped[..32]
+            .try_into()
+            .expect("0..32 doesn't span 32 bytes");
+        let mut shared = x25519(*sk, epk);
+        if shared == [0; 32] {
+            continue;
+        }
+        let aead = wrapping_key(&shared, &epk, &pk);
+        shared.erase();
+        let mut ck: [u8; 32] = wrapped[32..64]
+            .try_into()
+            .expect("32..64 doesn't span 32 bytes");
+        let tag: &Tag = wrapped[64..]
+            .try_into()
+            .expect("tag should be TAG_LEN bytes");
+        if aead
+            .decrypt_in_place_detached(&[0; 24], nonce, &mut ck, tag)
+            .is_ok()
+        {
+            return Ok(ck);
+        }
+    }
+    Err(Error::NotRecipient)
+}
+
+// returns the length of the chunk, and whether it is the last one.
+pub const fn frame_len(word: [u8; 4]) -> Result<(usize, bool), Error> {
+    let word = u32::from_le_bytes(word);
+    let len = (word & !FINAL) as usize;
+    if len > CHUNK_SIZE {
+        return Err(Error::Corrupt);
+    }
+    Ok((len, word & FINAL != 0))
+}
+
+// a rough estimate of the size of the content as it was sent, which is after
+// compression.
+pub const fn plaintext_len(envelope_len: u64) -> u64 {
+    const FRAME_OVERHEAD: u64 = (4 + TAG_LEN + SIGNATURE_LEN) as u64;
+    let frames = envelope_len.saturating_sub((1 + HEADER_LEN + SIGNATURE_LEN) as u64);
+    let full = frames / (CHUNK_SIZE as u64 + FRAME_OVERHEAD);
+    let rest = frames % (CHUNK_SIZE as u64 + FRAME_OVERHEAD);
+    full * CHUNK_SIZE as u64 + rest.saturating_sub(FRAME_OVERHEAD)
+}
+
+// the index goes into the last 8 bytes of the nonce, which leaves 128 random
+// bits to keep nonces apart across envelopes.
+fn chunk_nonce(nonce: &[u8; 24], index: u64) -> [u8; 24] {
+    let mut chunk_nonce = *nonce;
+    for (n, i) in chunk_nonce[16..].iter_mut().zip(index.to_le_bytes()) {
+        *n ^= i;
+    }
+    chunk_nonce
+}
+
+const NAME: u8 = 1;
+const CONTENT_TYPE: u8 = 2;
+const SIZE: u8 = 3;
+const MODE: u8 = 4;
+pub const MAX_FIELD_LEN: usize = u8::MAX as usize;
+
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub compression: Compression,
+    // the original file name, without any directories.
+    pub name: Option<String>,
+    pub content_type: Option<String>,
+    // the size before compression.
+    pub size: Option<u64>,
+    // the unix permission bits.
+    pub mode: Option<u32>,
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn field(header: &mut Vec<u8>, tag: u8, value: &[u8]) {
+            #[allow(clippy::cast_possible_truncation)] // checked when parsing arguments
+            let len = value.len().min(MAX_FIELD_LEN) as u8;
+            header.push(tag);
+            header.push(len);
+            header.extend_from_slice(&value[..len.into()]);
+        }
+        let mut header = vec![self.compression.id()];
+        if let Some(name) = &self.name {
+            field(&mut header, NAME, name.as_bytes());
+        }
+        if let Some(content_type) = &self.content_type {
+            field(&mut header, CONTENT_TYPE, content_type.as_bytes());
+        }
+        if let Some(size) = self.size {
+            field(&mut header, SIZE, &size.to_le_bytes());
+        }
+        if let Some(mode) = self.mode {
+            field(&mut header, MODE, &mode.to_le_bytes());
+        }
+        header
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let Some((&compression, mut fields)) = bytes.split_first() else {
+            return Err(Error::Corrupt);
+        };
+        let mut header = Self {
+            compression: Compression::from_id(compression)?,
+            name: None,
+            content_type: None,
+            size: None,
+            mode: None,
+        };
+        let string = |value: &[u8]| String::from_utf8(value.to_vec()).map_err(|_| Error::Corrupt);
+        while let [tag, len, rest @ ..] = fields {
+            let (value, rest) = rest.split_at_checked((*len).into()).ok_or(Error::Corrupt)?;
+            match *tag {
+                NAME => header.name = Some(string(value)?),
+                CONTENT_TYPE => header.content_type = Some(string(value)?),
+                SIZE => {
+                    let size = value.try_into().map_err(|_| Error::Corrupt)?;
+                    header.size = Some(u64::from_le_bytes(size));
+                }
+                MODE => {
+                    let mode = value.try_into().map_err(|_| Error::Corrupt)?;
+                    header.mode = Some(u32::from_le_bytes(mode));
+                }
+                _ => {}
+            }
+            fields = rest;
+        }
+        if !fields.is_empty() {
+            return Err(Error::Corrupt);
+        }
+        Ok(header)
+    }
+}
+
+pub struct Sealer<'a> {
+    sign_sk: &'a SigningKey,
+    aead: XChaCha20Poly1305,
+    nonce: [u8; 24],
+    index: u64,
+    digest: blake2b::State,
+}
+
+impl<'a> Sealer<'a> {
+    // encrypts with ek, or with a fresh key wrapped for each of `recipients` if
+    // there are any. the header that is returned, every chunk and the envelope
+    // as a whole are signed with `sign_sk`.
+    pub fn new(
+        config: &Config,
+        recipients: &[[u8; 32]],
+        sign_sk: &'a SigningKey,
+    ) -> Result<(Self, Vec<u8>), Error> {
+        let mut nonce = [0; 24];
+        rand_core::OsRng.fill_bytes(&mut nonce);
+        let mut header = Vec::with_capacity(1 + HEADER_LEN + SIGNATURE_LEN);
+        header.push(FORMAT);
+        let aead = if recipients.is_empty() {
+            header.extend_from_slice(&config.encrypt_sk_id().to_le_bytes());
+            header.extend_from_slice(&nonce);
+            XChaCha20Poly1305::new(&config.encrypt_sk()?)
+        } else {
+            if recipients.len() > MAX_RECIPIENTS {
+                return Err(Error::InvalidRecipient);
+            }
+            let mut ck = [0; 32];
+            rand_core::OsRng.fill_bytes(&mut ck);
+            header.extend_from_slice(&RECIPIENTS.to_le_bytes());
+            header.extend_from_slice(&nonce);
+            #[allow(clippy::cast_possible_truncation)] // checked above
+            header.extend_from_slice(&(recipients.len() as u32).to_le_bytes());
+            for pk in recipients {
+                header.extend_from_slice(&wrap(&ck, &nonce, pk)?);
+            }
+            let aead = XChaCha20Poly1305::new(&ck);
+            ck.erase();
+            aead
+        };
+        let header_signature = sign_sk.sign(header_digest(&header).as_bytes());
+        header.extend_from_slice(&header_signature.to_bytes());
+        let mut digest = digest();
+        digest.update(&header);
+        let sealer = Self {
+            sign_sk,
+            aead,
+            nonce,
+            index: 0,
+            digest,
+        };
+        Ok((sealer, header))
+    }
+
+    // encrypts `chunk` in place, and returns the length word that precedes it
+    // and the tag and signature that follow it on the wire.
+    pub fn seal(&mut self, chunk: &mut [u8], last: bool) -> ([u8; 4], Tag, [u8; SIGNATURE_LEN]) {
+        assert!(chunk.len() <= CHUNK_SIZE, "chunk too large");
+        #[allow(clippy::cast_possible_truncation)] // checked above
+        let mut word = chunk.len() as u32;
+        if last {
+            word |= FINAL;
+        }
+        let word = word.to_le_bytes();
+        let nonce = chunk_nonce(&self.nonce, self.index);
+        let tag = self.aead.encrypt_in_place_detached(&nonce, &word, chunk);
+        self.index += 1;
+        self.digest.update(&word);
+        self.digest.update(chunk);
+        self.digest.update(&tag);
+        let chunk_signature = self
+            .sign_sk
+            .sign(chunk_digest(&self.digest).as_bytes())
+            .to_bytes();
+        self.digest.update(&chunk_signature);
+        (word, tag, chunk_signature)
+    }
+
+    pub fn finish(self) -> Signature {
+        self.sign_sk.sign(self.digest.finalize().as_bytes())
+    }
+}
+
+pub struct Opener<'a> {
+    signer: &'a (String, VerifyingKey),
+    aead: XChaCha20Poly1305,
+    nonce: [u8; 24],
+    index: u64,
+    done: bool,
+    digest: blake2b::State,
+}
+
+impl<'a> Opener<'a> {
+    // `envelope_header` is everything up to the first frame, from the format to
+    // hs. nothing is opened unless hs was made by one of `sign_pks`.
+    pub fn new(
+        config: &Config,
+        sign_pks: &'a [(String, VerifyingKey)],
+        envelope_header: &[u8],
+    ) -> Result<Self, Error> {
+        let Some((&format, _)) = envelope_header.split_first() else {
+            return Err(Error::Short);
+        };
+        if format != FORMAT {
+            return Err(Error::UnknownFormat(format));
+        }
+        let (header_with_format, header_signature) = envelope_header
+            .split_last_chunk::<SIGNATURE_LEN>()
+            .ok_or(Error::Short)?;
+        let header = header_with_format.get(1..).ok_or(Error::Short)?;
+        if header.len() < HEADER_LEN {
+            return Err(Error::Short);
+        }
+        let signer = signer(
+            sign_pks,
+            header_digest(header_with_format).as_bytes(),
+            header_signature,
+        )?;
+        let encrypt_sk_id = {
+            let c = &header[..8];
+            [c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]]
+        };
+        let nonce = header[8..HEADER_LEN]
+            .try_into()
+            .expect("8..32 doesn't span 24 bytes. math has died.");
+        let aead = if encrypt_sk_id == RECIPIENTS.to_le_bytes() {
+            let recipient_sk = config
+                .recipient_sk()
+                .ok_or(Error::MissingField("recipient_sk"))?;
+            let recipients = header.get(HEADER_LEN + 4..).ok_or(Error::Short)?;
+            let mut ck = unwrap(recipients, &nonce, &recipient_sk)?;
+            let aead = XChaCha20Poly1305::new(&ck);
+            ck.erase();
+            aead
+        } else {
+            if encrypt_sk_id
+                .ct_eq(&config.encrypt_sk_id().to_le_bytes())
+                .to_u8()
+                != 1
+            {
+                return Err(Error::SecretKeyIDMismatch {
+                    expected: config.encrypt_sk_id(),
+                    actual: u64::from_le_bytes(encrypt_sk_id),
+                });
+            }
+            XChaCha20Poly1305::new(&config.encrypt_sk()?)
+        };
+        let mut digest = digest();
+        digest.update(envelope_header);
+        Ok(Self {
+            signer,
+            aead,
+            nonce,
+            index: 0,
+            done: false,
+            digest,
+        })
+    }
+
+    // `body` is the ciphertext of a chunk followed by its tag and signature. on
+    // success, the chunk is decrypted in place and the plaintext is returned.
+    pub fn open<'b>(&mut self, word: [u8; 4], body: &'b mut [u8]) -> Result<&'b [u8], Error> {
+        if self.done || body.len() < TAG_LEN + SIGNATURE_LEN {
+            return Err(Error::Corrupt);
+        }
+        let (body, chunk_signature) = body.split_at_mut(body.len() - SIGNATURE_LEN);
+        let (ct, tag) = body.split_at_mut(body.len() - TAG_LEN);
+        let (len, last) = frame_len(word)?;
+        if len != ct.len() {
+            return Err(Error::Corrupt);
+        }
+        let tag: &Tag = (&*tag).try_into().expect("tag should be TAG_LEN bytes");
+        let chunk_signature: &[u8; SIGNATURE_LEN] = (&*chunk_signature)
+            .try_into()
+            .expect("the chunk signature should be SIGNATURE_LEN bytes");
+        // the digest covers the ciphertext, so it has to be updated first.
+        self.digest.update(&word);
+        self.digest.update(ct);
+        self.digest.update(tag);
+        let (_, sign_pk) = self.signer;
+        sign_pk.verify_strict(
+            chunk_digest(&self.digest).as_bytes(),
+            &Signature::from_bytes(chunk_signature)?,
+        )?;
+        self.digest.update(chunk_signature);
+        let nonce = chunk_nonce(&self.nonce, self.index);
+        self.aead
+            .decrypt_in_place_detached(&nonce, &word, ct, tag)
+            .map_err(|_| Error::Corrupt)?;
+        self.index += 1;
+        self.done = last;
+        Ok(ct)
+    }
+
+    // returns the name of the key that signed the envelope, which has to be
+    // the one that signed the header.
+    pub fn finish(self, signature: &[u8; 64]) -> Result<&'a str, Error> {
+        if !self.done {
+            return Err(Error::Corrupt);
+        }
+        let (name, sign_pk) = self.signer;
+        let signature = Signature::from_bytes(signature)?;
+        sign_pk.verify_strict(self.digest.finalize().as_bytes(), &signature)?;
+        Ok(name)
+    }
+}
@@ -5,59 +5,98 @@ use std::{
 
 pub enum Error {
     Auth,
+    Banned,
     CapacityReached,
+    Corrupt,
     Empty,
+    Exists(std::path::PathBuf),
     IncompatibleVersions { client: u8, server: u8 },
     InvalidField(&'static str),
+    InvalidFileName(String),
+    InvalidRecipient,
     Io(std::io::Error),
     Large { max: u64, got: u64 },
-    MaybeIncompatibleVersion,
     MissingField(&'static str),
+    NoFileName,
     NoHome,
+    NotArchive,
+    NotRecipient,
     Old,
     ProtocolUnsupported,
+    Rejected,
     SecretKeyIDMismatch { expected: u64, actual: u64 },
     Short,
-    ShortCiphertext(u64),
     Signature,
     Toml(toml::de::Error),
+    UnknownCompression(u8),
+    UnknownFormat(u8),
     UnknownOp,
+    UnsafePath(String),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Auth => f.write_str("authentication failed"),
+            Self::Banned => f.write_str("too many failed attempts from this address"),
             Self::CapacityReached => f.write_str("cannot accept any more clients"),
+            Self::Corrupt => f.write_str("the clipboard content is corrupt or was tampered with"),
             Self::Empty => f.write_str("the clipboard may be empty"),
+            Self::Exists(path) => write!(
+                f,
+                "'{}' already exists (use --force to overwrite it)",
+                path.display()
+            ),
             Self::IncompatibleVersions { client, server } => write!(
                 f,
                 "incompatible server version (client: {client}, server: {server})"
             ),
             Self::InvalidField(field) => write!(f, "invalid value for config field `{field}`"),
+            Self::InvalidFileName(name) => write!(f, "'{name}' can't be used as a file name"),
+            Self::InvalidRecipient => f.write_str("invalid recipient public key"),
             Self::Io(e) => Display::fmt(e, f),
             Self::Large { max, got } => write!(
                 f,
                 "{got} bytes requested to be stored, but limit set to {max} bytes ({} MiB)",
                 max / (1024 * 1024)
             ),
-            Self::MaybeIncompatibleVersion => {
-                f.write_str("the server may be running an incompatible version")
-            }
             Self::MissingField(field) => write!(f, "missing required config field `{field}`"),
+            Self::NoFileName => {
+                f.write_str("the content was sent without a file name to save it under")
+            }
             Self::NoHome => f.write_str("could not determine home directory"),
+            Self::NotArchive => {
+                f.write_str("the clipboard content is not a directory sent with copy -r")
+            }
+            Self::NotRecipient => {
+                f.write_str("the content was not sent to the configured recipient key")
+            }
             Self::Old => f.write_str("the clipboard content is too old"),
             Self::ProtocolUnsupported => f.write_str("the server doesn't support this protocol"),
+            Self::Rejected => f.write_str("the server rejected the request"),
             Self::SecretKeyIDMismatch { expected, actual } => write!(
                 f,
                 "configured key ID is {expected:x}, but content was encrypted using key ID \
                  {actual:x}"
             ),
             Self::Short => f.write_str("the clipboard content is too short"),
-            Self::ShortCiphertext(len) => write!(f, "short encrypted message (only {len} bytes)"),
             Self::Signature => f.write_str("signature verification failed"),
             Self::Toml(e) => write!(f, "could not parse TOML config: {e}"),
+            Self::UnknownCompression(id) => {
+                write!(
+                    f,
+                    "the clipboard content uses an unknown compression method ({id})"
+                )
+            }
+            Self::UnknownFormat(format) => write!(
+                f,
+                "the clipboard content was sent in a format this version of klip can't read \
+                 ({format})"
+            ),
             Self::UnknownOp => f.write_str("unknown opcode"),
+            Self::UnsafePath(path) => {
+                write!(f, "refusing to extract '{path}', which is not a safe path")
+            }
         }
     }
 }
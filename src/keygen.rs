@@ -3,13 +3,13 @@ use rand_core::RngCore;
 use std::num::NonZeroU32;
 
 struct DeterministicRandom {
-    pool: [u8; 96],
+    pool: [u8; 128],
     pos: usize,
 }
 
 impl DeterministicRandom {
     pub fn init(key: &[u8]) -> Self {
-        let mut out = [0; 96];
+        let mut out = [0; 128];
         scrypt::scrypt(
             key,
             &[],
@@ -93,6 +93,7 @@ impl rand_core::RngCore for Rand {
 }
 
 impl rand_core::CryptoRng for Rand {}
+#[allow(clippy::similar_names)]
 pub fn generate_keys(config_file_name: impl std::fmt::Display, key: &[u8]) {
     let mut rng = if key.is_empty() {
         Rand::OsRng(rand_core::OsRng)
@@ -113,6 +114,15 @@ pub fn generate_keys(config_file_name: impl std::fmt::Display, key: &[u8]) {
     hex(signing_key.as_bytes(), &mut signing_key_hex);
     let mut verifying_key_hex = [0; 64];
     hex(verifying_key.as_bytes(), &mut verifying_key_hex);
+    // the recipient key is the one thing that is meant to be personal, so it is
+    // never derived from a password that others may know too.
+    let mut recipient_sk = [0; 32];
+    rand_core::OsRng.fill_bytes(&mut recipient_sk);
+    let mut recipient_sk_hex = [0; 64];
+    hex(&recipient_sk, &mut recipient_sk_hex);
+    let recipient_pk = curve25519::MontgomeryPoint::mul_base_clamped(recipient_sk);
+    let mut recipient_pk_hex = [0; 64];
+    hex(recipient_pk.as_bytes(), &mut recipient_pk_hex);
     println!(
         "\n\n--- Create a file named {config_file_name} with only the lines relevant to your \
          configuration ---\n\n"
@@ -126,6 +136,11 @@ pub fn generate_keys(config_file_name: impl std::fmt::Display, key: &[u8]) {
     println!("sign_pk    = \"{}\"", from_utf8(&verifying_key_hex));
     println!("sign_sk    = \"{}\"", from_utf8(&signing_key_hex));
     println!("encrypt_sk = \"{}\"", from_utf8(&encrypt_sk_hex));
+    println!("recipient_sk = \"{}\"", from_utf8(&recipient_sk_hex));
+    println!(
+        "# recipient_pk = \"{}\"\t# give this to whoever sends you content",
+        from_utf8(&recipient_pk_hex)
+    );
     println!();
     println!("# Configuration for a server\n");
     println!(
@@ -148,6 +163,11 @@ pub fn generate_keys(config_file_name: impl std::fmt::Display, key: &[u8]) {
     println!("sign_pk    = \"{}\"", from_utf8(&verifying_key_hex));
     println!("sign_sk    = \"{}\"", from_utf8(&signing_key_hex));
     println!("encrypt_sk = \"{}\"", from_utf8(&encrypt_sk_hex));
+    println!("recipient_sk = \"{}\"", from_utf8(&recipient_sk_hex));
+    println!(
+        "# recipient_pk = \"{}\"\t# give this to whoever sends you content",
+        from_utf8(&recipient_pk_hex)
+    );
 }
 
 #[inline]
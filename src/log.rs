@@ -0,0 +1,153 @@
+use crate::util::json_string;
+use std::{
+    fmt::Write as _,
+    io::Write as _,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Level {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+// set once the configuration is known, and again whenever it is reloaded.
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn init(level: Level, format: Format) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    JSON.store(format == Format::Json, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+// there is deliberately no way to log raw bytes, which is what keys, slot ids
+// and content all are.
+#[derive(Debug, Clone, Copy)]
+pub enum Value<'a> {
+    Str(&'a str),
+    Num(u64),
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(s: &'a str) -> Self {
+        Self::Str(s)
+    }
+}
+
+impl From<u64> for Value<'_> {
+    fn from(n: u64) -> Self {
+        Self::Num(n)
+    }
+}
+
+pub fn log(level: Level, msg: &str, fields: &[(&str, Value<'_>)]) {
+    if !enabled(level) {
+        return;
+    }
+    let ts = timestamp();
+    let mut line = String::new();
+    if JSON.load(Ordering::Relaxed) {
+        _ = write!(
+            line,
+            "{{\"ts\":\"{ts}\",\"level\":\"{}\",\"msg\":{}",
+            level.name(),
+            json_string(msg)
+        );
+        for (key, value) in fields {
+            _ = write!(line, ",{}:", json_string(key));
+            match value {
+                Value::Str(s) => line.push_str(&json_string(s)),
+                Value::Num(n) => _ = write!(line, "{n}"),
+            }
+        }
+        line.push('}');
+    } else {
+        _ = write!(line, "{ts} {:5} {msg}", level.name().to_uppercase());
+        for (key, value) in fields {
+            match value {
+                Value::Str(s) if needs_quotes(s) => _ = write!(line, " {key}={}", json_string(s)),
+                Value::Str(s) => _ = write!(line, " {key}={s}"),
+                Value::Num(n) => _ = write!(line, " {key}={n}"),
+            }
+        }
+    }
+    line.push('\n');
+    // a single write keeps lines from different connections apart.
+    _ = std::io::stderr().lock().write_all(line.as_bytes());
+}
+
+pub fn error(msg: &str, fields: &[(&str, Value<'_>)]) {
+    log(Level::Error, msg, fields);
+}
+
+pub fn warn(msg: &str, fields: &[(&str, Value<'_>)]) {
+    log(Level::Warn, msg, fields);
+}
+
+pub fn info(msg: &str, fields: &[(&str, Value<'_>)]) {
+    log(Level::Info, msg, fields);
+}
+
+pub fn debug(msg: &str, fields: &[(&str, Value<'_>)]) {
+    log(Level::Debug, msg, fields);
+}
+
+fn needs_quotes(s: &str) -> bool {
+    s.is_empty() || s.chars().any(|c| c <= ' ' || c == '"' || c == '=')
+}
+
+// RFC 3339, in UTC and with millisecond precision.
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let secs = secs % 86400;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        now.subsec_millis()
+    )
+}
+
+// days since the epoch to a proleptic gregorian date, after Howard Hinnant's
+// `civil_from_days`. only dates after the epoch ever come up here.
+const fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
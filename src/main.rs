@@ -31,20 +31,39 @@ const EXPANDED_VERSION: &str = concat!(
 );
 
 const DOMAIN: &str = "KLIP";
+// the oldest protocol version the client still speaks. the server goes back
+// further. the newest one is `default_client_version!()`.
+const MIN_PROTOCOL_VERSION: u8 = 2;
 const DEFAULT_LISTEN: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8075);
 const DEFAULT_CONNECT: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 8075);
 const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_HISTORY: usize = 1;
+// how long the server holds on to a watch request before answering it anyway,
+// which keeps idle connections from being dropped along the way.
+const WATCH_INTERVAL: Duration = Duration::from_secs(30);
 
+mod archive;
 mod authentication;
+mod ban;
+mod cidr;
 mod cli;
 mod client;
+mod compression;
 mod config;
+mod envelope;
 mod error;
 mod keygen;
+mod log;
+mod metrics;
+mod mime;
+mod output;
 mod server;
+mod session;
 mod state;
+mod storage;
 mod util;
 
+use clap::Parser;
 use cli::Cli;
 
 // since Rust no longer uses jemalloc by default, klip will, by default, use the
@@ -96,8 +115,14 @@ async fn shutdown() {
 async fn main() -> Result<(), error::Context> {
     #[cfg(windows)]
     platform::preflight();
+    let cli = Cli::parse();
+    // the server shuts down gracefully on its own. anything else may as well
+    // stop right away.
+    if matches!(cli.subcommand, cli::Command::Serve(_)) {
+        return cli.run().await;
+    }
     tokio::select! {
-        r = async { Cli::run().await } => r,
+        r = cli.run() => r,
         () = shutdown() => {
             eprintln!("violently shutting down");
             std::process::exit(1);
@@ -0,0 +1,276 @@
+use crate::{log, server::OPCODES, state::State, util::unix_time};
+use std::{
+    fmt::Write as _,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    time::{sleep, timeout},
+};
+
+// scrapers poll rarely, so anything past a handful at once is not one.
+const MAX_SCRAPES: usize = 4;
+const ACCEPT_RETRY_MIN: Duration = Duration::from_millis(10);
+const ACCEPT_RETRY_MAX: Duration = Duration::from_secs(1);
+
+// counters only ever go up, and are only read to be reported, so relaxed
+// ordering is plenty.
+#[derive(Default)]
+pub struct Metrics {
+    accepted: AtomicU64,
+    rejected: AtomicU64,
+    banned: AtomicU64,
+    denied: AtomicU64,
+    auth_failures: AtomicU64,
+    stored_bytes: AtomicU64,
+    fetched_bytes: AtomicU64,
+    requests: [AtomicU64; OPCODES.len()],
+}
+
+impl Metrics {
+    pub fn accepted(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self) {
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn banned(&self) {
+        self.banned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn denied(&self) {
+        self.denied.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn auth_failure(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stored(&self, bytes: u64) {
+        self.stored_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn fetched(&self, bytes: u64) {
+        self.fetched_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn request(&self, opcode: u8) {
+        if let Some(i) = OPCODES.iter().position(|&(op, _)| op == opcode) {
+            self.requests[i].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(out, "# HELP {name} {help}");
+    _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+// the text exposition format.
+fn render(state: &State) -> String {
+    let mut out = String::new();
+    counters(state, &mut out);
+    content(state, &mut out);
+    out
+}
+
+fn counters(state: &State, out: &mut String) {
+    let metrics = state.metrics();
+    for (name, help, counter) in [
+        (
+            "klip_connections_accepted_total",
+            "Client connections accepted.",
+            &metrics.accepted,
+        ),
+        (
+            "klip_connections_rejected_total",
+            "Client connections rejected because the server was at capacity.",
+            &metrics.rejected,
+        ),
+        (
+            "klip_connections_banned_total",
+            "Client connections refused because their address was banned.",
+            &metrics.banned,
+        ),
+        (
+            "klip_connections_denied_total",
+            "Client connections refused because of the allow and deny lists.",
+            &metrics.denied,
+        ),
+        (
+            "klip_auth_failures_total",
+            "Connections that failed to authenticate.",
+            &metrics.auth_failures,
+        ),
+        (
+            "klip_stored_bytes_total",
+            "Bytes of encrypted content stored.",
+            &metrics.stored_bytes,
+        ),
+        (
+            "klip_fetched_bytes_total",
+            "Bytes of encrypted content sent to clients.",
+            &metrics.fetched_bytes,
+        ),
+    ] {
+        metric(out, name, "counter", help);
+        _ = writeln!(out, "{name} {}", counter.load(Ordering::Relaxed));
+    }
+    metric(
+        out,
+        "klip_requests_total",
+        "counter",
+        "Authenticated requests, by operation.",
+    );
+    for ((_, op), count) in OPCODES.iter().zip(&metrics.requests) {
+        _ = writeln!(
+            out,
+            "klip_requests_total{{op=\"{op}\"}} {}",
+            count.load(Ordering::Relaxed)
+        );
+    }
+    metric(
+        out,
+        "klip_clients",
+        "gauge",
+        "Client connections currently open.",
+    );
+    _ = writeln!(out, "klip_clients {}", state.client_count());
+}
+
+// what is stored right now, per tenant.
+fn content(state: &State, out: &mut String) {
+    let now = unix_time();
+    let mut entries = String::new();
+    let mut bytes = String::new();
+    let mut newest = String::new();
+    let mut oldest = String::new();
+    let snapshot = state.snapshot();
+    for (tenant, clipboard) in snapshot.tenants() {
+        let name = tenant.name();
+        let slots = clipboard.slots.read();
+        let history = slots.values().flatten();
+        let (count, len, min_ts, max_ts) = history.fold(
+            (0, 0, u64::MAX, 0),
+            |(count, len, min_ts, max_ts), content| {
+                (
+                    count + 1,
+                    len + content.ciphertext_with_encrypt_sk_and_nonce.len() as u64,
+                    min_ts.min(content.received),
+                    max_ts.max(content.received),
+                )
+            },
+        );
+        drop(slots);
+        _ = writeln!(entries, "klip_content_entries{{tenant=\"{name}\"}} {count}");
+        _ = writeln!(bytes, "klip_content_bytes{{tenant=\"{name}\"}} {len}");
+        // an empty clipboard has no age at all.
+        if count > 0 {
+            let newest_age = now.saturating_sub(max_ts);
+            let oldest_age = now.saturating_sub(min_ts);
+            _ = writeln!(
+                newest,
+                "klip_content_newest_age_seconds{{tenant=\"{name}\"}} {newest_age}"
+            );
+            _ = writeln!(
+                oldest,
+                "klip_content_oldest_age_seconds{{tenant=\"{name}\"}} {oldest_age}"
+            );
+        }
+    }
+    for (name, help, samples) in [
+        ("klip_content_entries", "Entries currently stored.", entries),
+        (
+            "klip_content_bytes",
+            "Bytes of encrypted content currently stored.",
+            bytes,
+        ),
+        (
+            "klip_content_newest_age_seconds",
+            "Age of the newest entry stored.",
+            newest,
+        ),
+        (
+            "klip_content_oldest_age_seconds",
+            "Age of the oldest entry stored.",
+            oldest,
+        ),
+    ] {
+        metric(out, name, "gauge", help);
+        out.push_str(&samples);
+    }
+}
+
+// just enough HTTP to answer a scraper: the request line is all that matters,
+// and the connection is closed after every response.
+async fn respond(state: &State, mut conn: TcpStream) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = conn.read(&mut buf).await?;
+        if n == 0 || request.len() + n > 8192 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let line = request.split(|&b| b == b'\r').next().unwrap_or_default();
+    let mut parts = line.split(|&b| b == b' ');
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(b"/metrics")) => ("200 OK", render(state)),
+        (Some(b"GET"), _) => ("404 Not Found", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: \
+         {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    conn.write_all(response.as_bytes()).await?;
+    conn.shutdown().await
+}
+
+pub async fn serve(state: Arc<State>, listener: TcpListener) {
+    let scrapes = Arc::new(Semaphore::new(MAX_SCRAPES));
+    let mut retry = ACCEPT_RETRY_MIN;
+    loop {
+        let (conn, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                // running out of descriptors fails every accept until some
+                // are closed, so give them a chance to be.
+                log::warn(
+                    "metrics connection not accepted",
+                    &[("error", e.to_string().as_str().into())],
+                );
+                sleep(retry).await;
+                retry = (retry * 2).min(ACCEPT_RETRY_MAX);
+                continue;
+            }
+        };
+        retry = ACCEPT_RETRY_MIN;
+        if !state.snapshot().config().is_allowed(peer.ip()) {
+            log::debug(
+                "metrics connection denied",
+                &[("peer", peer.to_string().as_str().into())],
+            );
+            continue;
+        }
+        let Ok(permit) = scrapes.clone().try_acquire_owned() else {
+            continue;
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            let limit = state.snapshot().config().timeout();
+            _ = timeout(limit, respond(&state, conn)).await;
+            drop(permit);
+        });
+    }
+}
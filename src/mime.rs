@@ -0,0 +1,38 @@
+// content types that can be told apart by the bytes they start with.
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (b"\xfd7zXZ\x00", "application/x-xz"),
+    (b"BZh", "application/x-bzip2"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"\x7fELF", "application/x-executable"),
+];
+
+const OCTET_STREAM: &str = "application/octet-stream";
+
+// guesses the content type from the first block of the content, for when the
+// sender didn't give one.
+pub fn sniff(block: &[u8]) -> &'static str {
+    if let Some((_, content_type)) = MAGIC.iter().find(|(magic, _)| block.starts_with(magic)) {
+        return content_type;
+    }
+    if block.get(257..262) == Some(b"ustar") {
+        return "application/x-tar";
+    }
+    // the block may well end in the middle of a character.
+    let text = match std::str::from_utf8(block) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if text && !block.contains(&0) {
+        "text/plain; charset=utf-8"
+    } else {
+        OCTET_STREAM
+    }
+}
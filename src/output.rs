@@ -0,0 +1,134 @@
+use crate::{envelope::Header, error::Error, util::sync_dir};
+use rand_core::RngCore;
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
+
+// pasted content goes to a temporary file next to its destination, which is
+// only renamed into place once the signature has been checked. an interrupted
+// or rejected paste never leaves a partial file behind.
+#[derive(Debug)]
+pub struct Output {
+    tmp: PathBuf,
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+    mode: Option<u32>,
+    force: bool,
+}
+
+// the name the sender gave is only ever used as a single path component, so
+// that it can't climb out of the directory it is meant for.
+fn file_name(name: &str) -> Option<&str> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) if !name.contains(['/', '\\', '\0']) => Some(name),
+        _ => None,
+    }
+}
+
+impl Output {
+    // if `dest` is a directory, the content is saved in it under the name it
+    // was sent with. otherwise, `dest` is the path of the file itself.
+    pub fn create(dest: &Path, header: &Header, force: bool) -> Result<Self, Error> {
+        let is_dir = dest.is_dir() || dest.as_os_str().to_string_lossy().ends_with(['/', '\\']);
+        let path = if is_dir {
+            let name = header.name.as_deref().ok_or(Error::NoFileName)?;
+            let name = file_name(name).ok_or_else(|| Error::InvalidFileName(name.to_owned()))?;
+            dest.join(name)
+        } else {
+            dest.to_path_buf()
+        };
+        if !force && fs::symlink_metadata(&path).is_ok() {
+            return Err(Error::Exists(path));
+        }
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = path
+            .file_name()
+            .ok_or_else(|| Error::InvalidFileName(path.display().to_string()))?;
+        let tmp = dir.join(format!(
+            ".{}.{:016x}.tmp",
+            name.to_string_lossy(),
+            rand_core::OsRng.next_u64()
+        ));
+        // the content may well be meant for a file that only we can read, and is
+        // kept that way until its own mode is applied.
+        let file = platform::fs::create_private(&tmp)?;
+        Ok(Self {
+            tmp,
+            path,
+            file: Some(BufWriter::new(file)),
+            mode: header.mode,
+            force,
+        })
+    }
+
+    // fails early if `dest` is a file that exists already, before anything is
+    // retrieved. for a move, that would otherwise lose the content.
+    pub fn check(dest: &Path, force: bool) -> Result<(), Error> {
+        if !force && !dest.is_dir() && fs::symlink_metadata(dest).is_ok() {
+            return Err(Error::Exists(dest.to_path_buf()));
+        }
+        Ok(())
+    }
+
+    pub fn commit(mut self) -> Result<PathBuf, Error> {
+        let file = self
+            .file
+            .take()
+            .expect("output should only be committed once")
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?;
+        // setuid and friends don't survive the trip.
+        if let Some(mode) = self.mode {
+            platform::fs::set_mode(&file, mode & 0o777)?;
+        }
+        file.sync_all()?;
+        drop(file);
+        // the destination may have appeared while the content was coming in,
+        // and may still appear until the very last moment.
+        if self.force {
+            fs::rename(&self.tmp, &self.path)?;
+        } else {
+            platform::fs::rename_noreplace(&self.tmp, &self.path).map_err(|e| {
+                if e.kind() == io::ErrorKind::AlreadyExists {
+                    Error::Exists(self.path.clone())
+                } else {
+                    e.into()
+                }
+            })?;
+        }
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            sync_dir(dir)?;
+        }
+        Ok(std::mem::take(&mut self.path))
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file
+            .as_mut()
+            .expect("output is not written to after it is committed")
+            .write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file
+            .as_mut()
+            .expect("output is not written to after it is committed")
+            .flush()
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        // renaming it away is what makes a commit, so whatever is left over at
+        // this point is a failed one.
+        _ = fs::remove_file(&self.tmp);
+    }
+}
@@ -1,60 +1,176 @@
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use crate::{
-    authentication::{auth0, auth1, auth2get, auth2store, auth3get, auth3store},
-    error::Error,
-    state::{State, TS},
-    util::Stream,
+    authentication::{
+        auth0, auth1, auth2get, auth2history, auth2store, auth2store_v1, auth2watch, auth3get,
+        auth3history, auth3stat, auth3store, auth4move, auth5move,
+    },
+    cli::Cli,
+    config::{Config, Tenant},
+    envelope,
+    error::{Context, Error},
+    log,
+    session::Ephemeral,
+    state::{Clipboard, Content, SlotId, Snapshot, State},
+    util::{unix_time, Stream},
 };
 use crypto_common::constant_time::ConstantTimeEq;
-use rand_core::RngCore;
-use tokio::net::TcpListener;
+use ed25519::Signature;
+use tokio::{
+    net::TcpListener,
+    time::{timeout_at, Instant},
+};
+
+// version 1 clients are still served, even though clients don't speak it
+// anymore. they only have the one slot, which is separate from all others.
+const SERVER_MIN_VERSION: u8 = 1;
+const V1_SLOT: SlotId = [0; 32];
+const SERVER_MAX_VERSION: u8 = crate::default_client_version!();
+// the first version with the session layer.
+const SESSION_VERSION: u8 = 3;
+// the first version with the watch opcode.
+const WATCH_VERSION: u8 = 5;
+// the first version with the stat opcode.
+const STAT_VERSION: u8 = 6;
+// the first version where moved content is only removed once the client says
+// it was saved.
+const MOVE_VERSION: u8 = 8;
+
+// every opcode, along with the name it goes by in logs and metrics.
+pub const OPCODES: [(u8, &str); 6] = [
+    (b'S', "copy"),
+    (b'G', "paste"),
+    (b'M', "move"),
+    (b'H', "history"),
+    (b'W', "watch"),
+    (b'T', "status"),
+];
+
+// what is known about a connection so far, for the line that is logged once
+// it is over. none of it is secret.
+#[derive(Debug, Default)]
+pub struct Summary<'a> {
+    pub tenant: Option<&'a str>,
+    pub version: Option<u8>,
+    pub opcode: Option<u8>,
+}
+
+impl Summary<'_> {
+    pub fn op(&self) -> Option<&'static str> {
+        self.opcode.map(|opcode| {
+            OPCODES
+                .iter()
+                .find(|&&(op, _)| op == opcode)
+                .map_or("unknown", |&(_, name)| name)
+        })
+    }
+}
 
 struct Connection<'a> {
     stream: &'a mut Stream,
     state: &'a State,
+    config: &'a Config,
+    version: u8,
+    tenant: &'a Tenant,
+    clipboard: &'a Clipboard,
 }
 
 impl Connection<'_> {
     pub async fn get_operation(self, h1: &[u8], is_move: bool) -> Result<(), Error> {
-        let mut rbuf = [0; 32];
+        let mut rbuf = [0; 72];
         self.stream.read_exact(&mut rbuf).await?;
-        let h2 = rbuf;
+        let slot: SlotId = rbuf[..32].try_into().expect("0..32 doesn't span 32 bytes");
+        let index_buf: [u8; 8] = rbuf[32..40]
+            .try_into()
+            .expect("32..40 doesn't span 8 bytes");
+        let h2 = &rbuf[40..72];
+        let opcode = if is_move { b'M' } else { b'G' };
+        let wh2 = auth2get(self.tenant.psk(), h1, opcode, &slot, &index_buf);
+        if wh2.as_bytes().ct_eq(h2).to_u8() != 1 {
+            return Err(Error::Auth);
+        }
+        // anything past the end of the history is reported as empty, so there
+        // is no need to care about truncation on 32-bit targets.
+        let index = usize::try_from(u64::from_le_bytes(index_buf)).unwrap_or(usize::MAX);
+        self.get(h2, slot, index, is_move).await
+    }
+
+    pub async fn get_operation_v1(self, h1: &[u8], is_move: bool) -> Result<(), Error> {
+        let mut h2 = [0; 32];
+        self.stream.read_exact(&mut h2).await?;
         let opcode = if is_move { b'M' } else { b'G' };
-        let wh2 = auth2get(self.state.config().psk(), h1, opcode);
+        let wh2 = auth2get(self.tenant.psk(), h1, opcode, &[], &[]);
         if wh2.as_bytes().ct_eq(&h2).to_u8() != 1 {
             return Err(Error::Auth);
         }
-        let (ts, signature, ciphertext_with_encrypt_sk_and_nonce) = if is_move {
-            let mut content = self.state.content.write();
-            let mut ts = TS.write();
-            let ret = (
-                *ts,
-                content.signature,
-                content.ciphertext_with_encrypt_sk_and_nonce.clone(),
-            );
-            *ts = 0;
-            content.signature = [0; 64];
-            content.ciphertext_with_encrypt_sk_and_nonce.drain(..);
-            drop(content);
-            drop(ts);
-            ret
-        } else {
-            let content = self.state.content.read();
-            let ts = { *TS.read() };
-            let signature = content.signature;
-            let ciphertext_with_encrypt_sk_and_nonce =
-                content.ciphertext_with_encrypt_sk_and_nonce.clone();
-            drop(content);
-            (ts, signature, ciphertext_with_encrypt_sk_and_nonce)
+        self.get(&h2, V1_SLOT, 0, is_move).await
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    async fn get(
+        mut self,
+        h2: &[u8],
+        slot: SlotId,
+        index: usize,
+        is_move: bool,
+    ) -> Result<(), Error> {
+        // older clients only read the one format they wrote. anything else is
+        // left for clients that can make sense of it.
+        let format = envelope::implicit_format(self.version);
+        let check_format = |content: &Content| match format {
+            Some(format) if content.format() != format => {
+                Err(Error::UnknownFormat(content.format()))
+            }
+            _ => Ok(()),
         };
-        let signature = if signature == [0; 64] {
-            &[]
+        // older clients get moved content removed before it is even sent, newer
+        // ones tell us when they're done with it.
+        let acked = is_move && self.version >= MOVE_VERSION;
+        let content = if is_move && !acked {
+            let mut slots = self.clipboard.slots.write();
+            if let Some(content) = slots.get(&slot).and_then(|history| history.get(index)) {
+                check_format(content)?;
+            }
+            let content = slots
+                .get_mut(&slot)
+                .and_then(|history| history.remove(index));
+            if slots.get(&slot).is_some_and(VecDeque::is_empty) {
+                slots.remove(&slot);
+            }
+            drop(slots);
+            self.clipboard.changed(&slot);
+            content
         } else {
-            &signature[..]
+            self.clipboard
+                .slots
+                .read()
+                .get(&slot)
+                .and_then(|history| history.get(index))
+                .cloned()
         };
-        self.stream.set_timeout(self.state.config().data_timeout());
-        let h3 = auth3get(self.state.config().psk(), &h2, &ts.to_le_bytes(), signature);
+        if let Some(content) = &content {
+            check_format(content)?;
+        }
+        // the sweeper only runs every so often, so content that expired in the
+        // meantime has to be filtered out here as well.
+        let content = content.filter(|content| content.is_servable(self.tenant));
+        let (ts, signature, ciphertext_with_encrypt_sk_and_nonce) =
+            content.as_ref().map_or((0, &[][..], &[][..]), |content| {
+                let envelope = &content.ciphertext_with_encrypt_sk_and_nonce[..];
+                // the format is only sent to clients that sent it themselves.
+                let envelope = if format.is_some() {
+                    &envelope[1..]
+                } else {
+                    envelope
+                };
+                (content.ts, &content.signature[..], envelope)
+            });
+        self.stream.set_timeout(self.config.data_timeout());
+        let h3 = auth3get(self.tenant.psk(), h2, &ts.to_le_bytes(), signature);
         self.stream.write_all(h3.as_bytes()).await?;
         let ciphertext_with_encrypt_sk_and_nonce_len =
             ciphertext_with_encrypt_sk_and_nonce.len() as u64;
@@ -68,125 +184,580 @@ impl Connection<'_> {
         self.stream.write_all(&ts.to_le_bytes()).await?;
         self.stream.write_all(signature).await?;
         self.stream
-            .write_all(&ciphertext_with_encrypt_sk_and_nonce)
+            .write_all(ciphertext_with_encrypt_sk_and_nonce)
             .await?;
         self.stream.flush().await?;
+        self.state
+            .metrics()
+            .fetched(ciphertext_with_encrypt_sk_and_nonce_len);
+        if acked {
+            self.remove_operation(h3.as_bytes(), &slot, signature)
+                .await?;
+        }
         Ok(())
     }
 
-    #[allow(clippy::cast_possible_truncation)]
-    pub async fn store_operation(self, h1: &[u8]) -> Result<(), Error> {
-        let mut rbuf = [0; 112];
-        self.stream.read_exact(&mut rbuf).await?;
-        let h2 = &rbuf[..32];
-        let len_buf: [u8; 8] = [
-            rbuf[32], rbuf[33], rbuf[34], rbuf[35], rbuf[36], rbuf[37], rbuf[38], rbuf[39],
-        ];
-
-        let ciphertext_with_encrypt_sk_and_nonce_len = u64::from_le_bytes(len_buf);
-        if ciphertext_with_encrypt_sk_and_nonce_len < 32 {
-            return Err(Error::ShortCiphertext(
-                ciphertext_with_encrypt_sk_and_nonce_len,
-            ));
-        }
-        if self.state.config().max_len() > 0
-            && ciphertext_with_encrypt_sk_and_nonce_len > self.state.config().max_len()
-        {
-            return Err(Error::Large {
-                max: self.state.config().max_len(),
-                got: ciphertext_with_encrypt_sk_and_nonce_len,
+    // the second half of a move. the client only gets back to us once the
+    // content is safely stored on its end, and it is removed then. the history
+    // may have changed in the meantime, so the entry is looked up again.
+    async fn remove_operation(
+        &mut self,
+        h3: &[u8],
+        slot: &SlotId,
+        signature: &[u8],
+    ) -> Result<(), Error> {
+        let mut h4 = [0; 32];
+        self.stream.read_exact(&mut h4).await?;
+        let wh4 = auth4move(self.tenant.psk(), h3);
+        if wh4.as_bytes().ct_eq(&h4).to_u8() != 1 {
+            return Err(Error::Auth);
+        }
+        let removed = {
+            let mut slots = self.clipboard.slots.write();
+            let removed = slots.get_mut(slot).and_then(|history| {
+                let index = history
+                    .iter()
+                    .position(|content| content.signature[..] == *signature)?;
+                history.remove(index)
             });
+            if slots.get(slot).is_some_and(VecDeque::is_empty) {
+                slots.remove(slot);
+            }
+            drop(slots);
+            u8::from(removed.is_some())
+        };
+        if removed == 1 {
+            self.clipboard.changed(slot);
         }
+        let h5 = auth5move(self.tenant.psk(), &h4, removed);
+        self.stream.write_all(h5.as_bytes()).await?;
+        self.stream.write_all(&[removed]).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    pub async fn store_operation(mut self, h1: &[u8]) -> Result<(), Error> {
+        let mut rbuf = [0; 72];
+        self.stream.read_exact(&mut rbuf).await?;
+        let slot: SlotId = rbuf[..32].try_into().expect("0..32 doesn't span 32 bytes");
+        let h2 = &rbuf[32..64];
         let mut tsbuf = [0; 8];
-        tsbuf.copy_from_slice(&rbuf[40..48]);
+        tsbuf.copy_from_slice(&rbuf[64..72]);
         let ts = u64::from_le_bytes(tsbuf);
-        let mut signature = [0; 64];
-        signature.copy_from_slice(&rbuf[48..112]);
         let opcode = b'S';
-        let wh2 = auth2store(
-            self.state.config().psk(),
-            h1,
-            opcode,
-            &ts.to_le_bytes(),
+        let wh2 = auth2store(self.tenant.psk(), h1, opcode, &slot, &ts.to_le_bytes());
+        if wh2.as_bytes().ct_eq(h2).to_u8() != 1 {
+            return Err(Error::Auth);
+        }
+        self.stream.set_timeout(self.config.data_timeout());
+        let (mut ciphertext_with_encrypt_sk_and_nonce, header_len) = self.read_envelope().await?;
+        let mut signature = [0; 64];
+        self.stream.read_exact(&mut signature).await?;
+        let mut digest = envelope::digest();
+        digest.update(&ciphertext_with_encrypt_sk_and_nonce);
+        let (_, signer) = envelope::signer(
+            self.tenant.sign_pks(),
+            digest.finalize().as_bytes(),
             &signature,
+        )?;
+        // clients wouldn't open an envelope with a header that wasn't signed by
+        // the same key, so there is no point in keeping it.
+        if let Some(header_len) = header_len {
+            let (header, rest) = ciphertext_with_encrypt_sk_and_nonce.split_at(header_len);
+            let header_signature = rest[..envelope::SIGNATURE_LEN]
+                .try_into()
+                .expect("the header signature is read in full");
+            signer.verify_strict(
+                envelope::header_digest(header).as_bytes(),
+                &Signature::from_bytes(header_signature)?,
+            )?;
+        }
+        // every stored envelope starts with its format, which clients that
+        // speak an older version leave out.
+        if let Some(format) = envelope::implicit_format(self.version) {
+            ciphertext_with_encrypt_sk_and_nonce.insert(0, format);
+        }
+        let h3 = auth3store(self.tenant.psk(), h2, &signature);
+        self.store(
+            slot,
+            Content {
+                ts,
+                received: unix_time(),
+                signer: signer.to_bytes(),
+                signature,
+                ciphertext_with_encrypt_sk_and_nonce,
+            },
         );
+        self.stream.set_timeout(self.config.data_timeout());
+        self.stream.write_all(h3.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    // version 1 clients send the whole envelope in one go, with its length up
+    // front, and sign it as is.
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn store_operation_v1(self, h1: &[u8]) -> Result<(), Error> {
+        let mut rbuf = [0; 112];
+        self.stream.read_exact(&mut rbuf).await?;
+        let h2 = &rbuf[..32];
+        let len = u64::from_le_bytes(
+            rbuf[32..40]
+                .try_into()
+                .expect("32..40 doesn't span 8 bytes"),
+        );
+        let ts = &rbuf[40..48];
+        let signature: [u8; 64] = rbuf[48..112]
+            .try_into()
+            .expect("48..112 doesn't span 64 bytes");
+        let opcode = b'S';
+        let wh2 = auth2store_v1(self.tenant.psk(), h1, opcode, ts, &signature);
         if wh2.as_bytes().ct_eq(h2).to_u8() != 1 {
             return Err(Error::Auth);
         }
-        let mut ciphertext_with_encrypt_sk_and_nonce =
-            vec![0; ciphertext_with_encrypt_sk_and_nonce_len as usize];
-        self.stream.set_timeout(self.state.config().data_timeout());
+        if len < envelope::HEADER_LEN as u64 {
+            return Err(Error::Short);
+        }
+        let max_len = self.tenant.max_len();
+        if max_len > 0 && len > max_len {
+            return Err(Error::Large {
+                max: max_len,
+                got: len,
+            });
+        }
+        self.stream.set_timeout(self.config.data_timeout());
+        let mut ciphertext_with_encrypt_sk_and_nonce = vec![0; 1 + len as usize];
+        ciphertext_with_encrypt_sk_and_nonce[0] = envelope::FORMAT_UNCHUNKED;
         self.stream
-            .read_exact(&mut ciphertext_with_encrypt_sk_and_nonce)
+            .read_exact(&mut ciphertext_with_encrypt_sk_and_nonce[1..])
             .await?;
-        self.state.config().sign_pk().verify_strict(
-            &ciphertext_with_encrypt_sk_and_nonce[..],
-            &ed25519::Signature::from_bytes(&signature)?,
+        let (_, signer) = envelope::signer(
+            self.tenant.sign_pks(),
+            &ciphertext_with_encrypt_sk_and_nonce[1..],
+            &signature,
         )?;
-        let h3 = auth3store(self.state.config().psk(), h2);
+        // the signature was authenticated instead, so there is nothing to add.
+        let h3 = auth3store(self.tenant.psk(), h2, &[]);
+        self.store(
+            V1_SLOT,
+            Content {
+                ts: u64::from_le_bytes(ts.try_into().expect("40..48 doesn't span 8 bytes")),
+                received: unix_time(),
+                signer: signer.to_bytes(),
+                signature,
+                ciphertext_with_encrypt_sk_and_nonce,
+            },
+        );
+        self.stream.set_timeout(self.config.data_timeout());
+        self.stream.write_all(h3.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    fn store(&self, slot: SlotId, content: Content) {
+        let len = content.ciphertext_with_encrypt_sk_and_nonce.len() as u64;
         {
-            let mut content = self.state.content.write();
-            *TS.write() = ts;
-            content.signature = signature;
-            content.ciphertext_with_encrypt_sk_and_nonce = ciphertext_with_encrypt_sk_and_nonce;
+            let mut slots = self.clipboard.slots.write();
+            let history = slots.entry(slot).or_default();
+            history.push_front(content);
+            history.truncate(self.tenant.history());
+            drop(slots);
+        }
+        self.clipboard.changed(&slot);
+        self.state.metrics().stored(len);
+    }
+
+    // the length of the content isn't known up front, so the envelope is read
+    // frame by frame until the last one, enforcing the size limit as we go.
+    // also returns the length of the header that hs is a signature of, if
+    // there is one.
+    async fn read_envelope(&mut self) -> Result<(Vec<u8>, Option<usize>), Error> {
+        let max_len = self.tenant.max_len();
+        let mut envelope = Vec::new();
+        if envelope::implicit_format(self.version).is_none() {
+            let mut format = [0];
+            self.stream.read_exact(&mut format).await?;
+            if format[0] != envelope::FORMAT {
+                return Err(Error::UnknownFormat(format[0]));
+            }
+            envelope.push(format[0]);
+        }
+        let start = envelope.len();
+        envelope.resize(start + envelope::HEADER_LEN, 0);
+        self.stream.read_exact(&mut envelope[start..]).await?;
+        if envelope::has_recipients(&envelope[start..]) {
+            let mut count = [0; 4];
+            self.stream.read_exact(&mut count).await?;
+            let len = envelope::recipients_len(count)?;
+            envelope.extend_from_slice(&count);
+            let start = envelope.len();
+            envelope.resize(start + len, 0);
+            self.stream.read_exact(&mut envelope[start..]).await?;
+        }
+        let header_len = envelope::implicit_format(self.version)
+            .is_none()
+            .then_some(envelope.len());
+        if let Some(header_len) = header_len {
+            envelope.resize(header_len + envelope::SIGNATURE_LEN, 0);
+            self.stream.read_exact(&mut envelope[header_len..]).await?;
+        }
+        // chunks are only signed in envelopes that say what format they're in.
+        let chunk_signature_len = header_len.map_or(0, |_| envelope::SIGNATURE_LEN);
+        loop {
+            let mut word = [0; 4];
+            self.stream.read_exact(&mut word).await?;
+            let (len, last) = envelope::frame_len(word)?;
+            let start = envelope.len();
+            let end = start + word.len() + len + envelope::TAG_LEN + chunk_signature_len;
+            if max_len > 0 && end as u64 > max_len {
+                return Err(Error::Large {
+                    max: max_len,
+                    got: end as u64,
+                });
+            }
+            envelope.extend_from_slice(&word);
+            envelope.resize(end, 0);
+            self.stream
+                .read_exact(&mut envelope[start + word.len()..])
+                .await?;
+            if last {
+                return Ok((envelope, header_len));
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn history_operation(self, h1: &[u8]) -> Result<(), Error> {
+        let mut rbuf = [0; 64];
+        self.stream.read_exact(&mut rbuf).await?;
+        let slot: SlotId = rbuf[..32].try_into().expect("0..32 doesn't span 32 bytes");
+        let h2 = &rbuf[32..64];
+        let opcode = b'H';
+        let wh2 = auth2history(self.tenant.psk(), h1, opcode, &slot);
+        if wh2.as_bytes().ct_eq(h2).to_u8() != 1 {
+            return Err(Error::Auth);
+        }
+        let listing: Vec<(u64, u64)> = self
+            .clipboard
+            .slots
+            .read()
+            .get(&slot)
+            .map(|history| {
+                history
+                    .iter()
+                    .filter(|c| c.is_servable(self.tenant))
+                    .map(|c| (c.ts, c.ciphertext_with_encrypt_sk_and_nonce.len() as u64))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut entries = Vec::with_capacity(8 + 16 * listing.len());
+        entries.extend_from_slice(&(listing.len() as u64).to_le_bytes());
+        for (ts, len) in listing {
+            entries.extend_from_slice(&ts.to_le_bytes());
+            entries.extend_from_slice(&len.to_le_bytes());
+        }
+        self.stream.set_timeout(self.config.data_timeout());
+        let h3 = auth3history(self.tenant.psk(), h2, &entries);
+        self.stream.write_all(h3.as_bytes()).await?;
+        self.stream.write_all(&entries).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    // describes an entry without sending it.
+    pub async fn stat_operation(self, h1: &[u8]) -> Result<(), Error> {
+        let mut rbuf = [0; 72];
+        self.stream.read_exact(&mut rbuf).await?;
+        let slot: SlotId = rbuf[..32].try_into().expect("0..32 doesn't span 32 bytes");
+        let index_buf: [u8; 8] = rbuf[32..40]
+            .try_into()
+            .expect("32..40 doesn't span 8 bytes");
+        let h2 = &rbuf[40..72];
+        let opcode = b'T';
+        let wh2 = auth2get(self.tenant.psk(), h1, opcode, &slot, &index_buf);
+        if wh2.as_bytes().ct_eq(h2).to_u8() != 1 {
+            return Err(Error::Auth);
+        }
+        let index = usize::try_from(u64::from_le_bytes(index_buf)).unwrap_or(usize::MAX);
+        let mut stat = [0; 48];
+        if let Some(content) = self
+            .clipboard
+            .slots
+            .read()
+            .get(&slot)
+            .and_then(|history| history.get(index))
+            .filter(|content| content.is_servable(self.tenant))
+        {
+            let len = content.ciphertext_with_encrypt_sk_and_nonce.len() as u64;
+            stat[..8].copy_from_slice(&content.ts.to_le_bytes());
+            stat[8..16].copy_from_slice(&len.to_le_bytes());
+            stat[16..].copy_from_slice(&content.signer);
+        }
+        self.stream.set_timeout(self.config.data_timeout());
+        let h3 = auth3stat(self.tenant.psk(), h2, &stat);
+        self.stream.write_all(h3.as_bytes()).await?;
+        self.stream.write_all(&stat).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    // answers once the newest entry in the slot is no longer the one the
+    // client has seen, or after WATCH_INTERVAL regardless. entries are told
+    // apart by their signature, since several can share a timestamp.
+    pub async fn watch_operation(self, h1: &[u8]) -> Result<(), Error> {
+        let mut rbuf = [0; 128];
+        self.stream.read_exact(&mut rbuf).await?;
+        let slot: SlotId = rbuf[..32].try_into().expect("0..32 doesn't span 32 bytes");
+        let h2 = &rbuf[32..64];
+        let seen = &rbuf[64..128];
+        let opcode = b'W';
+        let wh2 = auth2watch(self.tenant.psk(), h1, opcode, &slot, seen);
+        if wh2.as_bytes().ct_eq(h2).to_u8() != 1 {
+            return Err(Error::Auth);
         }
-        self.stream.set_timeout(self.state.config().data_timeout());
+        // subscribing before looking means that no change can slip in between.
+        let mut changes = self.clipboard.subscribe();
+        let deadline = Instant::now() + crate::WATCH_INTERVAL;
+        let (ts, signature) = loop {
+            let latest = self.clipboard.latest(&slot, self.tenant);
+            if latest.1[..] != *seen || self.state.is_shutting_down() {
+                break latest;
+            }
+            match timeout_at(deadline, changes.changed()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) | Err(_) => break latest,
+            }
+        };
+        self.stream.set_timeout(self.config.data_timeout());
+        let h3 = auth3get(self.tenant.psk(), h2, &ts.to_le_bytes(), &signature);
         self.stream.write_all(h3.as_bytes()).await?;
+        self.stream.write_all(&ts.to_le_bytes()).await?;
+        self.stream.write_all(&signature).await?;
         self.stream.flush().await?;
         Ok(())
     }
 }
 
-pub async fn handle_connection(state: &State, stream: &mut Stream) -> Result<(), Error> {
-    let config = state.config();
-    let mut rbuf = [0; 65];
+pub async fn handle_connection<'a>(
+    state: &State,
+    snapshot: &'a Snapshot,
+    stream: &mut Stream,
+    summary: &mut Summary<'a>,
+) -> Result<(), Error> {
     let remote_addr = stream.peer_addr()?;
-    stream.read_exact(&mut rbuf).await?;
-    let client_version = rbuf[0];
-    if client_version != 1 {
-        return Err(Error::IncompatibleVersions {
-            client: client_version,
-            server: 1,
-        });
-    }
-    let r = &rbuf[1..33];
-    let h0 = &rbuf[33..65];
-    let wh0 = auth0(config.psk(), client_version, r);
-    if wh0.as_bytes().ct_eq(h0).to_u8() != 1 {
-        return Err(Error::Auth);
-    }
-    let mut r2 = [0; 32];
-    let mut rand = rand_core::OsRng;
-    rand.fill_bytes(&mut r2);
-    let h1 = auth1(config.psk(), client_version, h0, &r2);
-    stream.write_all(&[client_version]).await?;
+    let mut rbuf = [0; 66];
+    stream.read_exact(&mut rbuf[..1]).await?;
+    // version 1 clients send a single version byte instead of a range, so the
+    // rest of their message is one byte shorter.
+    let versions = if rbuf[0] == 1 {
+        stream.read_exact(&mut rbuf[2..]).await?;
+        rbuf[1] = 1;
+        &rbuf[..1]
+    } else {
+        stream.read_exact(&mut rbuf[1..]).await?;
+        &rbuf[..2]
+    };
+    let (client_min_version, client_max_version) = (rbuf[0], rbuf[1]);
+    let r = &rbuf[2..34];
+    let h0 = &rbuf[34..66];
+    // the tenant is whichever one's psk the client used. all of them are
+    // tried, so that the time this takes doesn't depend on which one it is.
+    let (tenant, clipboard) = snapshot
+        .tenants()
+        .fold(None, |found, (tenant, clipboard)| {
+            let wh0 = auth0(tenant.psk(), versions, r);
+            if wh0.as_bytes().ct_eq(h0).to_u8() == 1 {
+                Some((tenant, clipboard))
+            } else {
+                found
+            }
+        })
+        .ok_or(Error::Auth)?;
+    summary.tenant = Some(tenant.name());
+    // pick the newest version we have in common. if there is none, we still
+    // tell the client which one we would have wanted, so that it can report a
+    // meaningful error.
+    let version = client_max_version.min(SERVER_MAX_VERSION);
+    let compatible = version >= client_min_version.max(SERVER_MIN_VERSION);
+    let version = if compatible {
+        version
+    } else {
+        SERVER_MAX_VERSION
+    };
+    summary.version = Some(version);
+    let (ephemeral, r2) = Ephemeral::generate();
+    let h1 = auth1(tenant.psk(), version, h0, &r2);
+    stream.write_all(&[version]).await?;
     stream.write_all(&r2).await?;
     stream.write_all(h1.as_bytes()).await?;
     stream.flush().await?;
+    if !compatible {
+        return Err(Error::IncompatibleVersions {
+            client: client_max_version,
+            server: SERVER_MAX_VERSION,
+        });
+    }
+    // older clients send everything in the clear, and their r isn't a public
+    // key to begin with.
+    if version >= SESSION_VERSION {
+        stream.start_session(ephemeral.agree(r, h1.as_bytes(), false)?);
+    }
     state.add_trusted_ip(remote_addr.ip());
-    let conn = Connection { stream, state };
+    let conn = Connection {
+        stream,
+        state,
+        config: snapshot.config(),
+        version,
+        tenant,
+        clipboard,
+    };
     let mut opcode = [0];
     let opcode = conn
         .stream
         .read_exact(&mut opcode)
         .await
         .map(|_| opcode[0])?;
+    summary.opcode = Some(opcode);
+    state.metrics().request(opcode);
     match opcode {
+        b'G' if version == 1 => conn.get_operation_v1(h1.as_bytes(), false).await,
+        b'M' if version == 1 => conn.get_operation_v1(h1.as_bytes(), true).await,
+        b'S' if version == 1 => conn.store_operation_v1(h1.as_bytes()).await,
+        _ if version == 1 => Err(Error::UnknownOp),
         b'G' => conn.get_operation(h1.as_bytes(), false).await,
         b'M' => conn.get_operation(h1.as_bytes(), true).await,
         b'S' => conn.store_operation(h1.as_bytes()).await,
+        b'H' => conn.history_operation(h1.as_bytes()).await,
+        b'W' if version >= WATCH_VERSION => conn.watch_operation(h1.as_bytes()).await,
+        b'T' if version >= STAT_VERSION => conn.stat_operation(h1.as_bytes()).await,
         _ => Err(Error::UnknownOp),
     }
 }
 
-pub async fn serve(state: State) -> Result<(), Error> {
+// re-reads the configuration file whenever the server is sent SIGHUP. if it
+// turns out to be invalid, the configuration the server is running with stays
+// in place. addresses to listen on only take effect on the next restart.
+#[cfg(unix)]
+async fn reload_on_sighup(state: &State, cli: &Cli, config_file: &Path) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut signal = signal(SignalKind::hangup())?;
+    while signal.recv().await == Some(()) {
+        // loading new tenants reads their content from disk.
+        let ret = tokio::task::block_in_place(|| {
+            let config = cli.load_config(config_file)?;
+            let (level, format) = (config.log_level(), config.log_format());
+            state.reload(config)?;
+            Ok::<_, Context>((level, format))
+        });
+        match ret {
+            Ok((level, format)) => {
+                log::init(level, format);
+                let tenants = state.snapshot().config().tenants().len() as u64;
+                log::info("configuration reloaded", &[("tenants", tenants.into())]);
+            }
+            Err(e) => log::error(
+                "failed to reload configuration",
+                &[("error", e.to_string().as_str().into())],
+            ),
+        }
+    }
+    Ok(())
+}
+
+pub async fn serve(state: State, cli: Cli, config_file: PathBuf) -> Result<(), Error> {
     let state = Arc::new(state);
-    tokio::spawn(async move { State::handle_siginfo().await });
-    let listener = TcpListener::bind(state.config().listen()).await?;
+    #[cfg(unix)]
+    tokio::spawn({
+        let state = state.clone();
+        async move { reload_on_sighup(&state, &cli, &config_file).await }
+    });
+    #[cfg(not(unix))]
+    let _ = (cli, config_file);
+    tokio::spawn({
+        let state = state.clone();
+        async move { state.handle_siginfo().await }
+    });
+    tokio::spawn({
+        let state = state.clone();
+        async move { state.sweep_expired().await }
+    });
+    tokio::spawn({
+        let state = state.clone();
+        async move { state.expire_bans().await }
+    });
+    let snapshot = state.snapshot();
+    let listener = TcpListener::bind(snapshot.config().listen()).await?;
+    log::info(
+        "listening",
+        &[(
+            "addr",
+            snapshot.config().listen().to_string().as_str().into(),
+        )],
+    );
+    if let Some(addr) = snapshot.config().metrics_listen() {
+        let listener = TcpListener::bind(addr).await?;
+        log::info(
+            "serving metrics",
+            &[("addr", addr.to_string().as_str().into())],
+        );
+        tokio::spawn(crate::metrics::serve(state.clone(), listener));
+    }
+    let grace_period = snapshot.config().grace_period();
+    drop(snapshot);
+    let shutdown = crate::shutdown();
+    tokio::pin!(shutdown);
     loop {
-        let (conn, _) = listener.accept().await?;
-        if let Err(e) = state.clone().maybe_accept_client(conn) {
-            eprintln!("error: {e}");
+        let (conn, peer) = tokio::select! {
+            r = listener.accept() => r?,
+            () = &mut shutdown => break,
+        };
+        // the lists can change with every reload, so they are looked up anew
+        // for every connection.
+        if !state.snapshot().config().is_allowed(peer.ip()) {
+            state.metrics().denied();
+            log::debug(
+                "connection denied",
+                &[("peer", peer.to_string().as_str().into())],
+            );
+            continue;
+        }
+        if let Err(e) = state.clone().maybe_accept_client(conn, peer) {
+            // bans are logged once when they start, rather than for every
+            // connection they turn away.
+            let level = if matches!(e, Error::Banned) {
+                log::Level::Debug
+            } else {
+                log::Level::Warn
+            };
+            log::log(
+                level,
+                "connection rejected",
+                &[
+                    ("peer", peer.to_string().as_str().into()),
+                    ("error", e.to_string().as_str().into()),
+                ],
+            );
         }
     }
+    drop(listener);
+    log::info(
+        "shutting down",
+        &[("connections", (state.client_count() as u64).into())],
+    );
+    // a second signal cuts the wait short.
+    let open = tokio::select! {
+        open = state.drain(grace_period) => open,
+        () = crate::shutdown() => state.client_count(),
+    };
+    if open > 0 {
+        log::warn(
+            "closing connections",
+            &[("connections", (open as u64).into())],
+        );
+    }
+    if !state.flush() {
+        log::error("some clipboard content could not be persisted", &[]);
+    }
+    log::info("stopped", &[]);
+    Ok(())
 }
@@ -0,0 +1,136 @@
+use crate::{error::Error, DOMAIN};
+use crypto_common::erase::Erase;
+use curve25519::{montgomery::x25519, MontgomeryPoint};
+use rand_core::RngCore;
+use xchacha20poly1305::{Tag, XChaCha20Poly1305, TAG_SIZE};
+
+// once the handshake is done, everything else goes over the wire in records:
+//
+// record   := Len32(len) || ct || tag
+// ct, tag  := XChaCha20-Poly1305(key=k, nonce=Len(seq), ad=Len32(len))(m)
+//
+// every direction has its own key, derived from an ephemeral X25519 exchange
+// whose public keys are the r and r2 of the handshake, and its own sequence
+// number. a record that is dropped, replayed or reordered fails to open.
+pub const RECORD_SIZE: usize = 16 * 1024;
+
+// the ephemeral secret is erased as soon as the shared secret is computed, so
+// that a later compromise of the psk or of either side can't recover it.
+pub struct Ephemeral {
+    sk: [u8; 32],
+}
+
+impl Ephemeral {
+    pub fn generate() -> (Self, [u8; 32]) {
+        let mut sk = [0; 32];
+        rand_core::OsRng.fill_bytes(&mut sk);
+        let pk = MontgomeryPoint::mul_base_clamped(sk);
+        (Self { sk }, pk.to_bytes())
+    }
+
+    // `transcript` is h1, which covers both public keys as well as the psk.
+    pub fn agree(
+        self,
+        peer_pk: &[u8],
+        transcript: &[u8],
+        is_client: bool,
+    ) -> Result<Session, Error> {
+        let peer_pk = peer_pk.try_into().map_err(|_| Error::Auth)?;
+        let mut shared = x25519(self.sk, peer_pk);
+        // a peer that sends a point of small order would force a shared secret
+        // of zero, which must not be used as a key.
+        if shared == [0; 32] {
+            return Err(Error::Auth);
+        }
+        let mut hasher = blake2b::Params::new()
+            .personal(DOMAIN.as_bytes())
+            .salt(b"session")
+            .key(&shared)
+            .hash_length(64)
+            .to_state();
+        shared.erase();
+        hasher.update(transcript);
+        let hash = hasher.finalize();
+        let (c2s, s2c) = hash.as_array().split_at(32);
+        let (send, recv) = if is_client { (c2s, s2c) } else { (s2c, c2s) };
+        Ok(Session {
+            send: Direction::new(send),
+            recv: Direction::new(recv),
+        })
+    }
+}
+
+impl Drop for Ephemeral {
+    fn drop(&mut self) {
+        self.sk.erase();
+    }
+}
+
+pub struct Session {
+    send: Direction,
+    recv: Direction,
+}
+
+struct Direction {
+    aead: XChaCha20Poly1305,
+    seq: u64,
+}
+
+impl Direction {
+    fn new(key: &[u8]) -> Self {
+        Self {
+            aead: XChaCha20Poly1305::new(key.try_into().expect("key should be 32 bytes")),
+            seq: 0,
+        }
+    }
+
+    fn nonce(&mut self) -> [u8; 24] {
+        let mut nonce = [0; 24];
+        nonce[..8].copy_from_slice(&self.seq.to_le_bytes());
+        self.seq += 1;
+        nonce
+    }
+}
+
+impl Session {
+    // encrypts `buf` in place, returning the length word that goes in front of
+    // it and the tag that goes after it.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn seal(&mut self, buf: &mut [u8]) -> ([u8; 4], Tag) {
+        debug_assert!(buf.len() <= RECORD_SIZE);
+        let word = (buf.len() as u32).to_le_bytes();
+        let nonce = self.send.nonce();
+        let tag = self.send.aead.encrypt_in_place_detached(&nonce, &word, buf);
+        (word, tag)
+    }
+
+    pub fn record_len(word: [u8; 4]) -> std::io::Result<usize> {
+        let len = u32::from_le_bytes(word) as usize;
+        if len > RECORD_SIZE {
+            return Err(invalid_record());
+        }
+        Ok(len)
+    }
+
+    // `body` is the ciphertext followed by its tag. the plaintext is left in
+    // the first `body.len() - TAG_SIZE` bytes.
+    pub fn open(&mut self, word: [u8; 4], body: &mut [u8]) -> std::io::Result<()> {
+        let Some(split) = body.len().checked_sub(TAG_SIZE) else {
+            return Err(invalid_record());
+        };
+        let (ct, tag) = body.split_at_mut(split);
+        let tag: &Tag = (&*tag).try_into().expect("tag should be TAG_SIZE bytes");
+        let nonce = self.recv.nonce();
+        self.recv
+            .aead
+            .decrypt_in_place_detached(&nonce, &word, ct, tag)
+            .map_err(|_| invalid_record())
+    }
+}
+
+fn invalid_record() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "a record failed to authenticate",
+    )
+}
@@ -1,17 +1,29 @@
-use crate::{config::Config, error::Error, server::handle_connection, util::Stream};
-use parking_lot::RwLock;
+use crate::{
+    ban::Bans,
+    config::{Config, Tenant},
+    error::Error,
+    log,
+    metrics::Metrics,
+    server::{handle_connection, Summary},
+    storage::Storage,
+    util::Stream,
+};
+use crypto_common::erase::Erase;
+use parking_lot::{Mutex, RwLock};
 use std::{
-    collections::VecDeque,
-    net::IpAddr,
+    collections::{HashMap, HashSet, VecDeque},
+    net::{IpAddr, SocketAddr},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::TcpStream,
+    sync::{watch, Notify},
 };
-use tokio::net::TcpStream;
 
-// i gave up on borrow checker appeasement and made these global, sue me.
-pub static TS: RwLock<u64> = RwLock::new(0);
 #[cfg(any(
     target_os = "dragonfly",
     target_os = "freebsd",
@@ -21,39 +33,334 @@ pub static TS: RwLock<u64> = RwLock::new(0);
 ))]
 static ARGV0: std::sync::OnceLock<String> = std::sync::OnceLock::new();
 
+pub type SlotId = [u8; 32];
+
+// the upper bound on how long expired content can linger before the sweeper
+// gets to it. paste requests never see it regardless.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+// how late a ban may be reported as lifted. it stops applying right on time
+// regardless.
+const UNBAN_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
 pub struct Content {
+    // the client's timestamp, which is only ever handed back to clients.
+    pub ts: u64,
+    // when the server received the content, which is what it expires by.
+    pub received: u64,
+    // the verifying key the signature was checked against on store.
+    pub signer: [u8; 32],
     pub signature: [u8; 64],
     pub ciphertext_with_encrypt_sk_and_nonce: Vec<u8>,
 }
 
-pub struct State {
+// content leaves the server by being moved out, overwritten, pushed out of the
+// history, or expired. erasing it on drop covers all of these at once.
+impl Drop for Content {
+    fn drop(&mut self) {
+        self.signature.erase();
+        self.ciphertext_with_encrypt_sk_and_nonce.iter_mut().erase();
+    }
+}
+
+impl Content {
+    // the format of the envelope, which is the first thing in it.
+    pub fn format(&self) -> u8 {
+        self.ciphertext_with_encrypt_sk_and_nonce[0]
+    }
+
+    // content signed with a key that was revoked since is treated the same
+    // as expired content.
+    pub fn is_servable(&self, tenant: &Tenant) -> bool {
+        !tenant.is_expired(self.received) && tenant.is_trusted(&self.signer)
+    }
+}
+
+// the content of a single tenant. tenants never share any of it.
+pub struct Clipboard {
+    // slots are created on the first store and removed again once their last
+    // entry is moved out, so an absent slot and an empty one are
+    // indistinguishable to clients. the newest entry is at the front.
+    pub slots: RwLock<HashMap<SlotId, VecDeque<Content>>>,
+    storage: Option<Storage>,
+    // bumped on every change to any slot. watchers check the slot they care
+    // about whenever it is.
+    changes: watch::Sender<()>,
+    // slots whose last write to disk failed, to be tried again on shutdown.
+    dirty: Mutex<HashSet<SlotId>>,
+}
+
+impl Clipboard {
+    fn new(tenant: &Tenant) -> Result<Self, Error> {
+        let storage = tenant
+            .storage_dir()
+            .map(|dir| Storage::new(dir.to_path_buf()))
+            .transpose()?;
+        let slots = match &storage {
+            Some(storage) => storage.load(tenant.history())?,
+            None => HashMap::new(),
+        };
+        let clipboard = Self {
+            slots: RwLock::new(slots),
+            storage,
+            changes: watch::Sender::new(()),
+            dirty: Mutex::new(HashSet::new()),
+        };
+        // whatever expired or had its key revoked while the server was down
+        // shouldn't be served, nor stay on disk any longer.
+        clipboard.remove_expired(tenant);
+        Ok(clipboard)
+    }
+
+    fn remove_expired(&self, tenant: &Tenant) {
+        let mut expired = Vec::new();
+        let mut removed = 0;
+        self.slots.write().retain(|slot, history| {
+            let len = history.len();
+            history.retain(|content| content.is_servable(tenant));
+            if history.len() != len {
+                expired.push(*slot);
+                removed += (len - history.len()) as u64;
+            }
+            !history.is_empty()
+        });
+        if removed > 0 {
+            log::debug(
+                "removed expired content",
+                &[
+                    ("tenant", tenant.name().into()),
+                    ("entries", removed.into()),
+                ],
+            );
+        }
+        for slot in &expired {
+            self.changed(slot);
+        }
+    }
+
+    // to be called after every change to the history of `slot`.
+    pub fn changed(&self, slot: &SlotId) {
+        self.persist(slot);
+        self.changes.send_replace(());
+    }
+
+    // wakes up watchers without anything having changed, so that they can
+    // notice the server is shutting down.
+    fn wake(&self) {
+        self.changes.send_replace(());
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.changes.subscribe()
+    }
+
+    // the timestamp and signature of the newest entry that can be served from
+    // `slot`, or zeros if there is none.
+    pub fn latest(&self, slot: &SlotId, tenant: &Tenant) -> (u64, [u8; 64]) {
+        self.slots
+            .read()
+            .get(slot)
+            .and_then(|history| history.iter().find(|c| c.is_servable(tenant)))
+            .map_or((0, [0; 64]), |c| (c.ts, c.signature))
+    }
+
+    // writes the current history of `slot` to disk, if persistence is enabled.
+    // the client is only acknowledged afterwards, so this blocks in place rather
+    // than handing the write off to some background task.
+    fn persist(&self, slot: &SlotId) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let ret = tokio::task::block_in_place(|| {
+            storage.save(slot, || self.slots.read().get(slot).cloned())
+        });
+        if let Err(e) = ret {
+            log::error(
+                "failed to persist clipboard content",
+                &[("error", e.to_string().as_str().into())],
+            );
+            self.dirty.lock().insert(*slot);
+        } else {
+            self.dirty.lock().remove(slot);
+        }
+    }
+
+    // makes up for earlier writes that failed, if there were any. returns
+    // whether everything is on disk now.
+    fn flush(&self) -> bool {
+        let dirty = std::mem::take(&mut *self.dirty.lock());
+        for slot in &dirty {
+            self.persist(slot);
+        }
+        self.dirty.lock().is_empty()
+    }
+}
+
+// the configuration, along with the clipboards of its tenants. reloading the
+// configuration replaces the whole thing at once, so a connection that holds on
+// to one keeps a consistent view of both until it is done.
+pub struct Snapshot {
     config: Config,
+    // one per tenant, in the same order as `config.tenants()`. they are shared
+    // with the snapshots that come after this one, as long as the tenant stays.
+    clipboards: Vec<Arc<Clipboard>>,
+}
+
+impl Snapshot {
+    pub const fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn tenants(&self) -> impl Iterator<Item = (&Tenant, &Clipboard)> {
+        self.config
+            .tenants()
+            .iter()
+            .zip(self.clipboards.iter().map(AsRef::as_ref))
+    }
+}
+
+pub struct State {
+    snapshot: RwLock<Arc<Snapshot>>,
     trusted_clients: RwLock<VecDeque<IpAddr>>,
     client_count: AtomicUsize,
-    pub content: Arc<RwLock<Content>>,
+    // notified whenever the last open connection closes.
+    idle: Notify,
+    shutting_down: AtomicBool,
+    bans: Bans,
+    metrics: Metrics,
 }
 
 impl State {
-    pub fn new(config: Config) -> Self {
+    pub fn new(config: Config) -> Result<Self, Error> {
         let cap = config.trusted_ip_count();
-        Self {
-            config,
+        let clipboards = config
+            .tenants()
+            .iter()
+            .map(|tenant| Clipboard::new(tenant).map(Arc::new))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            snapshot: RwLock::new(Arc::new(Snapshot { config, clipboards })),
             trusted_clients: RwLock::new(VecDeque::with_capacity(cap)),
             client_count: AtomicUsize::new(0),
-            content: Arc::new(RwLock::new(Content {
-                signature: [0; 64],
-                ciphertext_with_encrypt_sk_and_nonce: Vec::new(),
-            })),
+            idle: Notify::new(),
+            shutting_down: AtomicBool::new(false),
+            bans: Bans::default(),
+            metrics: Metrics::default(),
+        })
+    }
+
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.snapshot.read().clone()
+    }
+
+    // a tenant keeps its clipboard across the reload as long as its name and
+    // storage directory stay the same, even if its keys change. other tenants
+    // start over from whatever is in their storage directory, if anything.
+    pub fn reload(&self, config: Config) -> Result<(), Error> {
+        let old = self.snapshot();
+        let clipboards = config
+            .tenants()
+            .iter()
+            .map(|tenant| {
+                let kept = old.config.tenants().iter().position(|other| {
+                    other.name() == tenant.name() && other.storage_dir() == tenant.storage_dir()
+                });
+                kept.map_or_else(
+                    || Clipboard::new(tenant).map(Arc::new),
+                    |i| Ok(old.clipboards[i].clone()),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        let snapshot = Arc::new(Snapshot { config, clipboards });
+        *self.snapshot.write() = snapshot.clone();
+        // keys may have been revoked, or limits lowered.
+        for (tenant, clipboard) in snapshot.tenants() {
+            clipboard.remove_expired(tenant);
         }
+        Ok(())
     }
 
-    pub const fn config(&self) -> &Config {
-        &self.config
+    pub const fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.client_count.load(Ordering::SeqCst)
+    }
+
+    fn release_client(&self) {
+        if self.client_count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    // to be called once no new connections are accepted any more. watchers
+    // are answered right away instead of holding on to their connection, and
+    // the rest get until `grace` runs out to finish. returns the number of
+    // connections that are still open after that.
+    pub async fn drain(&self, grace: Duration) -> usize {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        for (_, clipboard) in self.snapshot().tenants() {
+            clipboard.wake();
+        }
+        let deadline = tokio::time::Instant::now() + grace;
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            // registers for the notification before looking, so that it can't
+            // slip by in between.
+            idle.as_mut().enable();
+            let count = self.client_count();
+            if count == 0 || tokio::time::timeout_at(deadline, idle).await.is_err() {
+                return self.client_count();
+            }
+        }
+    }
+
+    // returns whether all of the content is on disk, if it is meant to be.
+    pub fn flush(&self) -> bool {
+        tokio::task::block_in_place(|| {
+            self.snapshot()
+                .tenants()
+                .fold(true, |ok, (_, clipboard)| clipboard.flush() && ok)
+        })
+    }
+
+    // the limits may change with every reload, so they are looked up again
+    // every time.
+    pub async fn sweep_expired(&self) {
+        loop {
+            let max_ttl = self
+                .snapshot()
+                .config
+                .tenants()
+                .iter()
+                .map(Tenant::max_ttl)
+                .min()
+                .unwrap_or(SWEEP_INTERVAL);
+            tokio::time::sleep(SWEEP_INTERVAL.min(max_ttl)).await;
+            let snapshot = self.snapshot();
+            for (tenant, clipboard) in snapshot.tenants() {
+                clipboard.remove_expired(tenant);
+            }
+        }
+    }
+
+    pub async fn expire_bans(&self) {
+        let mut interval = tokio::time::interval(UNBAN_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.bans.expire();
+        }
     }
 
     pub fn add_trusted_ip(&self, ip: IpAddr) {
         let mut lock = self.trusted_clients.write();
-        if lock.len() >= self.config.trusted_ip_count() {
+        if lock.len() >= self.snapshot().config.trusted_ip_count() {
             lock.pop_front();
         }
         lock.push_back(ip);
@@ -64,31 +371,59 @@ impl State {
         g.is_empty() || g.contains(&ip)
     }
 
-    pub fn accept_client(self: Arc<Self>, mut conn: Stream) {
-        let fut = async move {
-            if let Err(e) = handle_connection(&self, &mut conn).await {
-                self.client_count.fetch_sub(1, Ordering::SeqCst);
-                conn.shutdown().await?;
-                return Err(e);
-            }
-            self.client_count.fetch_sub(1, Ordering::SeqCst);
-            Ok(())
-        };
+    pub fn accept_client(self: Arc<Self>, mut conn: Stream, peer: SocketAddr) {
         tokio::spawn(async move {
-            if let Err(e) = fut.await {
-                eprintln!("error: {e}");
+            let start = Instant::now();
+            // the connection sticks with the configuration it started out
+            // with, even if it is reloaded in the meantime.
+            let snapshot = self.snapshot();
+            let mut summary = Summary::default();
+            let ret = handle_connection(&self, &snapshot, &mut conn, &mut summary).await;
+            if matches!(ret, Err(Error::Auth)) {
+                self.metrics.auth_failure();
             }
+            log_connection(peer, &summary, &conn, start, &ret);
+            // a client that never gets as far as sending an opcode is held
+            // against its address just like a wrong key, since it ties up a
+            // slot all the same.
+            let failed = match &ret {
+                Err(Error::Auth) => true,
+                Err(Error::Io(e)) => {
+                    e.kind() == std::io::ErrorKind::TimedOut && summary.opcode.is_none()
+                }
+                _ => false,
+            };
+            if failed {
+                self.bans.failure(peer.ip());
+            } else if summary.opcode.is_some() {
+                self.bans.success(peer.ip());
+            }
+            if ret.is_err() {
+                _ = conn.shutdown().await;
+            }
+            self.release_client();
         });
     }
 
-    pub fn maybe_accept_client(self: Arc<Self>, conn: TcpStream) -> Result<(), Error> {
-        let remote_ip = conn.peer_addr()?.ip();
+    pub fn maybe_accept_client(
+        self: Arc<Self>,
+        conn: TcpStream,
+        peer: SocketAddr,
+    ) -> Result<(), Error> {
+        let remote_ip = peer.ip();
+        let trusted = self.trusted_clients.read().contains(&remote_ip);
+        if self.bans.is_banned(remote_ip, trusted) {
+            self.metrics.banned();
+            return Err(Error::Banned);
+        }
+        let snapshot = self.snapshot();
         let mut count;
         loop {
             count = self.client_count.load(Ordering::SeqCst);
-            if count >= self.config.max_clients() - self.config.trusted_ip_count()
+            if count >= snapshot.config.max_clients() - snapshot.config.trusted_ip_count()
                 && !self.is_trusted_ip(remote_ip)
             {
+                self.metrics.rejected();
                 return Err(Error::CapacityReached);
             }
             if self
@@ -99,9 +434,10 @@ impl State {
                 break;
             }
         }
+        self.metrics.accepted();
         let mut conn = Stream::new(conn);
-        conn.set_timeout(self.config().timeout());
-        self.accept_client(conn);
+        conn.set_timeout(snapshot.config.timeout());
+        self.accept_client(conn, peer);
         Ok(())
     }
 
@@ -113,9 +449,9 @@ impl State {
             target_os = "netbsd",
             target_os = "openbsd"
         )),
-        allow(clippy::unnecessary_wraps, clippy::unused_async)
+        allow(clippy::unnecessary_wraps, clippy::unused_async, clippy::unused_self)
     )]
-    pub async fn handle_siginfo() -> std::io::Result<()> {
+    pub async fn handle_siginfo(&self) -> std::io::Result<()> {
         #[cfg(any(
             target_os = "dragonfly",
             target_os = "freebsd",
@@ -124,30 +460,34 @@ impl State {
             target_os = "openbsd"
         ))]
         {
-            use std::{
-                borrow::Cow,
-                time::{Duration, SystemTime, UNIX_EPOCH},
-            };
+            use crate::util::human_age;
+            use std::time::{Duration, SystemTime, UNIX_EPOCH};
             use tokio::signal::unix::{signal, SignalKind};
             let mut signal = signal(SignalKind::info())?;
             while signal.recv().await == Some(()) {
                 let name = ARGV0
                     .get_or_init(|| std::env::args().next().unwrap_or_else(|| "klip".to_owned()));
-                let value = *TS.read();
-                match value {
+                let (count, latest) =
+                    self.snapshot()
+                        .tenants()
+                        .fold((0, 0), |(count, latest), (_, clipboard)| {
+                            let slots = clipboard.slots.read();
+                            let ts = slots
+                                .values()
+                                .filter_map(VecDeque::front)
+                                .map(|c| c.received)
+                                .max()
+                                .unwrap_or(0);
+                            (count + slots.len(), latest.max(ts))
+                        });
+                match latest {
                     0 => println!("{name}: the clipboard is empty"),
                     ts => {
                         let elapsed = SystemTime::now()
                             .duration_since(UNIX_EPOCH + Duration::from_secs(ts))
-                            .unwrap_or_default()
-                            .as_secs()
-                            / 60;
-                        let msg = if elapsed <= 1 {
-                            Cow::Borrowed("a few moments ago")
-                        } else {
-                            Cow::Owned(format!("{elapsed} minutes ago"))
-                        };
-                        println!("{name}: the clipboard is not empty (last filled {msg})");
+                            .unwrap_or_default();
+                        let msg = human_age(elapsed);
+                        println!("{name}: {count} clipboard slot(s) in use (last filled {msg})");
                     }
                 }
             }
@@ -165,3 +505,41 @@ impl State {
         }
     }
 }
+
+fn log_connection(
+    peer: SocketAddr,
+    summary: &Summary<'_>,
+    conn: &Stream,
+    start: Instant,
+    ret: &Result<(), Error>,
+) {
+    let peer = peer.to_string();
+    let duration_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX);
+    let mut fields = vec![("peer", peer.as_str().into())];
+    if let Some(tenant) = summary.tenant {
+        fields.push(("tenant", tenant.into()));
+    }
+    if let Some(version) = summary.version {
+        fields.push(("version", u64::from(version).into()));
+    }
+    if let Some(op) = summary.op() {
+        fields.push(("op", op.into()));
+    }
+    fields.extend([
+        ("bytes_in", conn.bytes_read().into()),
+        ("bytes_out", conn.bytes_written().into()),
+        ("duration_ms", duration_ms.into()),
+    ]);
+    match ret {
+        Ok(()) => {
+            fields.push(("outcome", "ok".into()));
+            log::info("connection", &fields);
+        }
+        Err(e) => {
+            let e = e.to_string();
+            fields.push(("outcome", "error".into()));
+            fields.push(("error", e.as_str().into()));
+            log::warn("connection", &fields);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    log,
+    state::{Content, SlotId},
+    util::{from_hex, hex, sync_dir},
+};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"KLIP";
+// earlier versions only held envelopes in formats that no current client can
+// open, so they are skipped rather than converted.
+const FORMAT_VERSION: u8 = 4;
+
+// every slot lives in its own file, named after the hex-encoded slot ID. the
+// file holds the whole history of the slot, newest entry first:
+//
+// "KLIP" || format version || Len(count) || (received || ts || pk || s || Len(ct) || ct)*
+//
+// where ct is the envelope, starting with its own format.
+//
+// everything stored here was already encrypted and signed by the client, so
+// nothing in this directory is any more sensitive than what goes over the wire.
+pub struct Storage {
+    dir: PathBuf,
+    // writes to the same slot must land in the order their snapshots were taken,
+    // so snapshotting and writing happen under this lock.
+    lock: Mutex<()>,
+}
+
+impl Storage {
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    pub fn load(&self, max_history: usize) -> io::Result<HashMap<SlotId, VecDeque<Content>>> {
+        let mut slots = HashMap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let mut slot = [0; 32];
+            // skips leftover temporary files as well as anything else that
+            // doesn't belong to us.
+            let Some(Ok(())) = name.to_str().map(|n| from_hex(n, &mut slot)) else {
+                continue;
+            };
+            match read_history(&entry.path()) {
+                Ok(mut history) if !history.is_empty() => {
+                    history.truncate(max_history);
+                    slots.insert(slot, history);
+                }
+                Ok(_) => {}
+                Err(e) => log::warn(
+                    "ignoring unreadable slot file",
+                    &[
+                        ("path", entry.path().to_string_lossy().as_ref().into()),
+                        ("error", e.to_string().as_str().into()),
+                    ],
+                ),
+            }
+        }
+        Ok(slots)
+    }
+
+    // `snapshot` is called with the lock held, and should return the current
+    // history of the slot, or `None` if the slot no longer exists.
+    pub fn save(
+        &self,
+        slot: &SlotId,
+        snapshot: impl FnOnce() -> Option<VecDeque<Content>>,
+    ) -> io::Result<()> {
+        let mut name = [0; 64];
+        hex(slot, &mut name);
+        let name = std::str::from_utf8(&name).expect("hex should be valid utf8");
+        let path = self.dir.join(name);
+        let guard = self.lock.lock();
+        let Some(history) = snapshot() else {
+            let ret = match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => sync_dir(&self.dir),
+            };
+            drop(guard);
+            return ret;
+        };
+        let tmp = self.dir.join(format!(".{name}.tmp"));
+        let ret = write_history(&tmp, &history)
+            .and_then(|()| fs::rename(&tmp, &path))
+            .and_then(|()| sync_dir(&self.dir));
+        drop(guard);
+        if ret.is_err() {
+            _ = fs::remove_file(&tmp);
+        }
+        ret
+    }
+}
+
+fn write_history(path: &Path, history: &VecDeque<Content>) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut w = BufWriter::new(file);
+    w.write_all(MAGIC)?;
+    w.write_all(&[FORMAT_VERSION])?;
+    w.write_all(&(history.len() as u64).to_le_bytes())?;
+    for content in history {
+        let ct = &content.ciphertext_with_encrypt_sk_and_nonce;
+        w.write_all(&content.received.to_le_bytes())?;
+        w.write_all(&content.ts.to_le_bytes())?;
+        w.write_all(&content.signer)?;
+        w.write_all(&content.signature)?;
+        w.write_all(&(ct.len() as u64).to_le_bytes())?;
+        w.write_all(ct)?;
+    }
+    w.into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()
+}
+
+fn read_history(path: &Path) -> io::Result<VecDeque<Content>> {
+    fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+        let mut buf = [0; 8];
+        r.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut r = BufReader::new(file);
+    let mut header = [0; 5];
+    r.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(invalid("bad magic"));
+    }
+    if header[4] != FORMAT_VERSION {
+        return Err(invalid("unsupported format version"));
+    }
+    let count = read_u64(&mut r)?;
+    let mut history = VecDeque::new();
+    for _ in 0..count {
+        let received = read_u64(&mut r)?;
+        let ts = read_u64(&mut r)?;
+        let mut signer = [0; 32];
+        r.read_exact(&mut signer)?;
+        let mut signature = [0; 64];
+        r.read_exact(&mut signature)?;
+        let len = read_u64(&mut r)?;
+        // don't trust the length field with an allocation larger than the file.
+        if len > file_len {
+            return Err(invalid("truncated entry"));
+        }
+        let mut ciphertext_with_encrypt_sk_and_nonce =
+            vec![0; usize::try_from(len).map_err(|_| invalid("entry too large"))?];
+        r.read_exact(&mut ciphertext_with_encrypt_sk_and_nonce)?;
+        if ciphertext_with_encrypt_sk_and_nonce.is_empty() {
+            return Err(invalid("empty entry"));
+        }
+        history.push_back(Content {
+            ts,
+            received,
+            signer,
+            signature,
+            ciphertext_with_encrypt_sk_and_nonce,
+        });
+    }
+    Ok(history)
+}
@@ -1,13 +1,32 @@
-use std::time::Duration;
+use crate::session::{Session, RECORD_SIZE};
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    fs::File,
+    future::Future,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufStream},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
     net::TcpStream,
     time::{timeout_at, Instant},
 };
+use xchacha20poly1305::TAG_SIZE;
 
 pub struct Stream {
     inner: BufStream<TcpStream>,
     timeout: Option<Instant>,
+    // once a session is established, writes are buffered up to a record and
+    // reads are served from the last record that was opened.
+    session: Option<Session>,
+    wbuf: Vec<u8>,
+    rbuf: Vec<u8>,
+    rpos: usize,
+    // what the connection carried, not counting the session's own overhead.
+    read: u64,
+    written: u64,
 }
 
 macro_rules! timed_out {
@@ -16,11 +35,28 @@ macro_rules! timed_out {
     };
 }
 
+async fn timed<T>(
+    timeout: Option<Instant>,
+    fut: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    if let Some(timeout) = timeout {
+        timeout_at(timeout, fut).await.map_err(|_| timed_out!())?
+    } else {
+        fut.await
+    }
+}
+
 impl Stream {
     pub fn new(stream: TcpStream) -> Self {
         Self {
             inner: BufStream::new(stream),
             timeout: None,
+            session: None,
+            wbuf: Vec::new(),
+            rbuf: Vec::new(),
+            rpos: 0,
+            read: 0,
+            written: 0,
         }
     }
 
@@ -28,51 +64,121 @@ impl Stream {
         self.timeout = Some(Instant::now() + dur);
     }
 
-    pub async fn read_exact(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        if let Some(timeout) = self.timeout {
-            timeout_at(timeout, self.inner.read_exact(buf))
-                .await
-                .map_err(|_| timed_out!())?
-        } else {
-            self.inner.read_exact(buf).await
+    // everything read or written from here on goes through the session.
+    pub fn start_session(&mut self, session: Session) {
+        self.session = Some(session);
+    }
+
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.session.is_none() {
+            let n = timed(self.timeout, self.inner.read_exact(buf)).await?;
+            self.read += n as u64;
+            return Ok(n);
+        }
+        let mut filled = 0;
+        while filled < buf.len() {
+            if self.rpos == self.rbuf.len() && !self.read_record().await? {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let n = (buf.len() - filled).min(self.rbuf.len() - self.rpos);
+            buf[filled..filled + n].copy_from_slice(&self.rbuf[self.rpos..self.rpos + n]);
+            filled += n;
+            self.rpos += n;
+        }
+        self.read += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    pub async fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.session.is_none() {
+            let n = timed(self.timeout, self.inner.read_to_end(buf)).await?;
+            self.read += n as u64;
+            return Ok(n);
+        }
+        let start = buf.len();
+        loop {
+            buf.extend_from_slice(&self.rbuf[self.rpos..]);
+            self.rpos = self.rbuf.len();
+            if !self.read_record().await? {
+                let n = buf.len() - start;
+                self.read += n as u64;
+                return Ok(n);
+            }
         }
     }
 
-    pub async fn read_to_end(&mut self, buf: &mut Vec<u8>) -> std::io::Result<usize> {
-        if let Some(timeout) = self.timeout {
-            timeout_at(timeout, self.inner.read_to_end(buf))
-                .await
-                .map_err(|_| timed_out!())?
-        } else {
-            self.inner.read_to_end(buf).await
+    // returns `false` if the peer closed the connection. that is only allowed
+    // to happen between records.
+    async fn read_record(&mut self) -> io::Result<bool> {
+        let timeout = self.timeout;
+        let eof = timed(timeout, async {
+            self.inner.fill_buf().await.map(<[u8]>::is_empty)
+        })
+        .await?;
+        if eof {
+            return Ok(false);
         }
+        let session = self.session.as_mut().expect("no session to read from");
+        let mut word = [0; 4];
+        timed(timeout, self.inner.read_exact(&mut word)).await?;
+        let len = Session::record_len(word)?;
+        self.rbuf.resize(len + TAG_SIZE, 0);
+        timed(timeout, self.inner.read_exact(&mut self.rbuf)).await?;
+        session.open(word, &mut self.rbuf)?;
+        self.rbuf.truncate(len);
+        self.rpos = 0;
+        Ok(true)
     }
 
-    pub async fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
-        if let Some(timeout) = self.timeout {
-            timeout_at(timeout, self.inner.write_all(buf))
-                .await
-                .map_err(|_| timed_out!())?
-        } else {
-            self.inner.write_all(buf).await
+    pub async fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        self.written += buf.len() as u64;
+        if self.session.is_none() {
+            return timed(self.timeout, self.inner.write_all(buf)).await;
         }
+        while !buf.is_empty() {
+            let n = (RECORD_SIZE - self.wbuf.len()).min(buf.len());
+            self.wbuf.extend_from_slice(&buf[..n]);
+            buf = &buf[n..];
+            if self.wbuf.len() == RECORD_SIZE {
+                self.write_record().await?;
+            }
+        }
+        Ok(())
     }
 
-    pub async fn flush(&mut self) -> std::io::Result<()> {
-        if let Some(timeout) = self.timeout {
-            timeout_at(timeout, self.inner.flush())
-                .await
-                .map_err(|_| timed_out!())?
-        } else {
-            self.inner.flush().await
+    async fn write_record(&mut self) -> io::Result<()> {
+        let session = self.session.as_mut().expect("no session to write to");
+        let (word, tag) = session.seal(&mut self.wbuf);
+        timed(self.timeout, async {
+            self.inner.write_all(&word).await?;
+            self.inner.write_all(&self.wbuf).await?;
+            self.inner.write_all(&tag).await
+        })
+        .await?;
+        self.wbuf.clear();
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> io::Result<()> {
+        if !self.wbuf.is_empty() {
+            self.write_record().await?;
         }
+        timed(self.timeout, self.inner.flush()).await
     }
 
-    pub fn peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+    pub fn peer_addr(&self) -> io::Result<std::net::SocketAddr> {
         self.inner.get_ref().peer_addr()
     }
 
-    pub async fn shutdown(mut self) -> std::io::Result<()> {
+    pub const fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    pub const fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    pub async fn shutdown(mut self) -> io::Result<()> {
         self.inner.shutdown().await
     }
 }
@@ -147,3 +253,70 @@ pub fn from_hex(s: &str, buf: &mut [u8]) -> Result<(), ()> {
     }
     Ok(())
 }
+
+// seconds since the epoch, by our own clock.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+pub fn human_age(elapsed: Duration) -> Cow<'static, str> {
+    let minutes = elapsed.as_secs() / 60;
+    match minutes {
+        0..=1 => Cow::Borrowed("a few moments ago"),
+        2..=119 => Cow::Owned(format!("{minutes} minutes ago")),
+        120..=2879 => Cow::Owned(format!("{} hours ago", minutes / 60)),
+        _ => Cow::Owned(format!("{} days ago", minutes / (24 * 60))),
+    }
+}
+
+// quotes `s` as a JSON string.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[allow(clippy::cast_precision_loss)]
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+// makes the rename itself durable. windows has no equivalent for directories,
+// and NTFS journals metadata anyway.
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        File::open(dir)?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        _ = dir;
+        Ok(())
+    }
+}
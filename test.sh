@@ -34,6 +34,22 @@ ${KLIP_C} paste | ${KLIP_C} copy
 ${KLIP_C} move > /tmp/kl2
 cmp /tmp/kl /tmp/kl2
 ${KLIP_C} paste && exit 1
+rm -rf "${TMPDIR}/klip-test-src" "${TMPDIR}/klip-test-dest"
+mkdir -p "${TMPDIR}/klip-test-src/tree/sub/empty" "${TMPDIR}/klip-test-dest"
+cp /tmp/kl "${TMPDIR}/klip-test-src/tree/sub/kl"
+echo hello > "${TMPDIR}/klip-test-src/tree/hello.txt"
+chmod 600 "${TMPDIR}/klip-test-src/tree/hello.txt"
+ln -s ../hello.txt "${TMPDIR}/klip-test-src/tree/sub/link"
+${KLIP_C} copy -r "${TMPDIR}/klip-test-src/tree"
+${KLIP_C} paste -r -o "${TMPDIR}/klip-test-dest"
+diff -r --no-dereference "${TMPDIR}/klip-test-src/tree" "${TMPDIR}/klip-test-dest/tree"
+test "$(stat -c %a "${TMPDIR}/klip-test-dest/tree/hello.txt")" = 600
+${KLIP_C} paste -r -o "${TMPDIR}/klip-test-dest" && exit 1
+${KLIP_C} move -r -o "${TMPDIR}/klip-test-dest" && exit 1
+${KLIP_C} move -r -o "${TMPDIR}/klip-test-dest" --force
+diff -r --no-dereference "${TMPDIR}/klip-test-src/tree" "${TMPDIR}/klip-test-dest/tree"
+${KLIP_C} paste && exit 1
+rm -rf "${TMPDIR}/klip-test-src" "${TMPDIR}/klip-test-dest"
 kill $pid
 wait $pid || true
 